@@ -1,11 +1,11 @@
-os=Linux # use your distro to get more specific instructions
-bash=Bash
-model=open-aigpt35-turbo
+# Generated by shai {{version}} with `shai generate-script --shell bash`, regenerate it instead of editing the shai flags
 
 _ask_assistant() {
     tmpf="$(mktemp)"
     printf '%s\n' "$READLINE_LINE" > "$tmpf"
-    "shai" "ask" "--operating-system" "$os" "--shell" "$shell" "--model" "$model" "--edit-file" "$tmpf"
+    # bash only writes the commands of this session to HISTFILE when it exits
+    history -a
+    shai {{ask_args}} --history-file "$HISTFILE" --edit-file "$tmpf"
     READLINE_LINE="$(<"$tmpf")"
     READLINE_POINT="${#READLINE_LINE}"
     rm -f "$tmpf"
@@ -14,12 +14,83 @@ _ask_assistant() {
 _explain_assistant() {
     tmpf="$(mktemp)"
     printf '%s\n' "$READLINE_LINE" > "$tmpf"
-    "shai" "explain" "--operating-system" "$os" "--shell" "$shell" "--model" "$model" "--edit-file" "$tmpf"
+    history -a
+    shai {{explain_args}} --history-file "$HISTFILE" --edit-file "$tmpf"
+    READLINE_LINE="$(<"$tmpf")"
+    READLINE_POINT="${#READLINE_LINE}"
+    rm -f "$tmpf"
+}
+
+_insert_assistant() {
+    tmpf="$(mktemp)"
+    printf '%s\n' "$READLINE_LINE" > "$tmpf"
+    # READLINE_POINT counts bytes and shai counts characters, the x keeps trailing newlines
+    prefix="$(LC_ALL=C; printf '%sx' "${READLINE_LINE:0:READLINE_POINT}")"
+    history -a
+    shai {{ask_args}} --history-file "$HISTFILE" --edit-file "$tmpf" --cursor "$((${#prefix} - 1))"
+    READLINE_LINE="$(<"$tmpf")"
+    if [[ -f "$tmpf.json" && "$(<"$tmpf.json")" =~ \"cursor_byte\":([0-9]+) ]]; then
+        READLINE_POINT="${BASH_REMATCH[1]}"
+    fi
+    rm -f "$tmpf" "$tmpf.json"
+}
+
+# Remember the last command that failed and its exit status so that shai can fix it.
+# shai-capture writes the error output to the first file, the two are swapped when the command
+# fails so it is kept. Both are private to this shell and removed when it exits.
+if [[ -z "$_shai_stderr" ]]; then
+    _shai_capture="$(mktemp)"
+    _shai_stderr="$(mktemp)"
+    # keep the EXIT trap that was already set, if any
+    _shai_exit_trap="$(trap -p EXIT)"
+    _shai_exit_trap="${_shai_exit_trap#trap -- }"
+    trap "rm -f \"\$_shai_capture\" \"\$_shai_stderr\"; eval ${_shai_exit_trap% EXIT}" EXIT
+fi
+
+# Run a command as `shai-capture <command>` to also give shai the end of its error output
+shai-capture() {
+    "$@" 2> >(tee "$_shai_capture" >&2)
+}
+
+_shai_record_status() {
+    local last_status=$? last_command kept
+    [[ "$last_status" -ne 0 ]] || return 0
+    # `fc -ln -1` would skip it here, `history` lists it after its number
+    last_command="$(HISTTIMEFORMAT= history 1)"
+    [[ "$last_command" =~ ^\ *([0-9]+)\*?\ +(.*)$ ]] || return "$last_status"
+    # an empty line keeps the status and the history of the command before it
+    [[ "${BASH_REMATCH[1]}" != "$_shai_last_number" ]] || return "$last_status"
+    _shai_last_number="${BASH_REMATCH[1]}"
+    _shai_last_status=$last_status
+    _shai_last_command="${BASH_REMATCH[2]}"
+    # the error output of any other command would be misleading
+    if [[ "$_shai_last_command" == shai-capture\ * ]]; then
+        # tee may still be writing it, so it is swapped rather than copied
+        kept="$_shai_capture"
+        _shai_capture="$_shai_stderr"
+        _shai_stderr="$kept"
+    else
+        : >| "$_shai_stderr"
+    fi
+    return "$last_status"
+}
+if [[ "$PROMPT_COMMAND" != *_shai_record_status* ]]; then
+    PROMPT_COMMAND="_shai_record_status${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+
+_fix_assistant() {
+    [[ -n "$_shai_last_command" ]] || return
+    tmpf="$(mktemp)"
+    printf '%s\n' "$READLINE_LINE" > "$tmpf"
+    history -a
+    shai {{fix_args}} --history-file "$HISTFILE" --edit-file "$tmpf" --command "${_shai_last_command#shai-capture }" --status "$_shai_last_status" --stderr-file "$_shai_stderr"
     READLINE_LINE="$(<"$tmpf")"
     READLINE_POINT="${#READLINE_LINE}"
     rm -f "$tmpf"
 }
 
 # Bind to trigger the _assistant_complete function
-bind -x '"\es":_ask_assistant'
-bind -x '"\ee":_explain_assistant'
+bind -x '"{{ask_key}}":_ask_assistant'
+bind -x '"{{explain_key}}":_explain_assistant'
+bind -x '"{{insert_key}}":_insert_assistant'
+bind -x '"{{fix_key}}":_fix_assistant'
@@ -1,6 +1,4 @@
-set os "Linux" # use your distro to get more specific instructions
-set shell "Fish"
-set model "open-aigpt35-turbo"
+# Generated by shai {{version}} with `shai generate-script --shell fish`, regenerate it instead of editing the shai flags
 
 function ask_assistant --description 'Edit command in Shai'
     set -l f (mktemp)
@@ -15,7 +13,7 @@ function ask_assistant --description 'Edit command in Shai'
 
     set -l p (commandline -C)
     commandline -b > $f
-    shai ask --operating-system "$os" --shell "$shell" --model $model --edit-file $f
+    shai {{ask_args}} --edit-file $f
 
     commandline -r (cat $f)
     commandline -C $p
@@ -35,12 +33,69 @@ function explain_assistant --description 'Let Shai explain this command'
 
     set -l p (commandline -C)
     commandline -b > $f
-    shai explain --operating-system "$os" --shell "$shell" --model $model --edit-file $f
+    shai {{explain_args}} --edit-file $f
 
     commandline -r (cat $f)
     commandline -C $p
     command rm $f
 end
 
-bind \es ask_assistant
-bind \ee explain_assistant
+function insert_assistant --description 'Let Shai insert a command fragment at the cursor'
+    set -l f (mktemp)
+    commandline -b > $f
+    shai {{ask_args}} --edit-file $f --cursor (commandline -C)
+
+    commandline -r (cat $f | string collect)
+    if test -f $f.json
+        set -l cursor (string match -r '"cursor":(\d+)' < $f.json)[2]
+        commandline -C $cursor
+    end
+    command rm -f $f $f.json
+end
+
+# Remember the last command that failed and its exit status so that shai can fix it.
+# shai-capture writes the error output to the first file, the two are swapped when the command
+# fails so it is kept. Both are private to this shell and removed when it exits.
+if not set -q _shai_stderr
+    set -g _shai_capture (mktemp)
+    set -g _shai_stderr (mktemp)
+end
+
+function shai-capture --description 'Run a command keeping the end of its error output for Shai'
+    $argv 2>| tee $_shai_capture 1>&2
+    return $pipestatus[1]
+end
+
+function _shai_record_status --on-event fish_postexec
+    set -l last_status $status
+    test -n "$argv[1]"; and test $last_status -ne 0; or return
+    set -g _shai_last_status $last_status
+    set -g _shai_last_command $argv[1]
+    # the error output of any other command would be misleading
+    if string match -q 'shai-capture *' -- $argv[1]
+        set -l kept $_shai_capture
+        set -g _shai_capture $_shai_stderr
+        set -g _shai_stderr $kept
+    else
+        printf '' >$_shai_stderr
+    end
+end
+
+function _shai_remove_stderr --on-event fish_exit
+    command rm -f $_shai_capture $_shai_stderr
+end
+
+function fix_assistant --description 'Let Shai fix the last command that failed'
+    set -q _shai_last_command; or return
+    set -l f (mktemp)
+    commandline -b > $f
+    shai {{fix_args}} --edit-file $f --command (string replace -r '^shai-capture ' '' -- $_shai_last_command | string collect) --status $_shai_last_status --stderr-file $_shai_stderr
+
+    commandline -r (cat $f | string collect)
+    command rm -f $f
+end
+
+bind {{ask_key}} ask_assistant
+bind {{explain_key}} explain_assistant
+bind {{insert_key}} insert_assistant
+bind {{fix_key}} fix_assistant
@@ -1,8 +1,6 @@
-$os = "Windows 11"
-$shell = "PowerShell"
-$model = "open-aigpt35-turbo"
+# Generated by shai {{version}} with `shai generate-script --shell power-shell`, regenerate it instead of editing the shai flags
 
-Set-PSReadlineKeyHandler -Key 'Alt+s' -ScriptBlock {
+Set-PSReadlineKeyHandler -Key '{{ask_key}}' -ScriptBlock {
     # Generate a unique temporary file name
     $tempFileName = [System.IO.Path]::GetTempFileName()
 
@@ -11,7 +9,7 @@ Set-PSReadlineKeyHandler -Key 'Alt+s' -ScriptBlock {
     # Write the buffer content to the temporary file
     $bufferState | Out-File -FilePath $tempFileName -Force
     # Run the CLI application with the temporary file
-    Start-Process shai -ArgumentList "ask --operating-system `"$os`" --shell `"$shell`" --model $model --edit-file $tempFileName" -Wait
+    Start-Process shai -ArgumentList "{{ask_args}} --edit-file $tempFileName" -Wait
     $fileContents = Get-Content -Raw -Path $tempFileName
     # # Remove the temporary file
     Remove-Item -Path $tempFileName -Force
@@ -20,7 +18,7 @@ Set-PSReadlineKeyHandler -Key 'Alt+s' -ScriptBlock {
     [Microsoft.PowerShell.PSConsoleReadLine]::Insert($fileContents)
 }
 
-Set-PSReadlineKeyHandler -Key 'Alt+e' -ScriptBlock {
+Set-PSReadlineKeyHandler -Key '{{explain_key}}' -ScriptBlock {
     # Generate a unique temporary file name
     $tempFileName = [System.IO.Path]::GetTempFileName()
 
@@ -29,7 +27,7 @@ Set-PSReadlineKeyHandler -Key 'Alt+e' -ScriptBlock {
     # Write the buffer content to the temporary file
     $bufferState | Out-File -FilePath $tempFileName -Force
     # Run the CLI application with the temporary file
-    Start-Process shai -ArgumentList "explain --operating-system `"$os`" --shell `"$shell`" --model $model --edit-file $tempFileName" -Wait
+    Start-Process shai -ArgumentList "{{explain_args}} --edit-file $tempFileName" -Wait
     $fileContents = Get-Content -Raw -Path $tempFileName
     # # Remove the temporary file
     Remove-Item -Path $tempFileName -Force
@@ -38,3 +36,61 @@ Set-PSReadlineKeyHandler -Key 'Alt+e' -ScriptBlock {
     [Microsoft.PowerShell.PSConsoleReadLine]::Insert($fileContents)
 }
 
+Set-PSReadlineKeyHandler -Key '{{insert_key}}' -ScriptBlock {
+    # Generate a unique temporary file name
+    $tempFileName = [System.IO.Path]::GetTempFileName()
+
+    $bufferState = $cursorState = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref] $bufferState, [ref] $cursorState)
+    # Write the buffer content to the temporary file
+    $bufferState | Out-File -FilePath $tempFileName -Force
+    # Run the CLI application with the temporary file and the cursor position
+    Start-Process shai -ArgumentList "{{ask_args}} --edit-file $tempFileName --cursor $cursorState" -Wait
+    $fileContents = (Get-Content -Raw -Path $tempFileName).TrimEnd()
+    [Microsoft.PowerShell.PSConsoleReadLine]::BackwardKillLine()
+    [Microsoft.PowerShell.PSConsoleReadLine]::KillLine()
+    [Microsoft.PowerShell.PSConsoleReadLine]::Insert($fileContents)
+    # Place the cursor after the inserted text
+    if (Test-Path "$tempFileName.json") {
+        $sidecar = Get-Content -Raw -Path "$tempFileName.json" | ConvertFrom-Json
+        [Microsoft.PowerShell.PSConsoleReadLine]::SetCursorPosition($sidecar.cursor)
+        Remove-Item -Path "$tempFileName.json" -Force
+    } else {
+        [Microsoft.PowerShell.PSConsoleReadLine]::SetCursorPosition($cursorState)
+    }
+    # # Remove the temporary file
+    Remove-Item -Path $tempFileName -Force
+}
+
+# Remember whether the last command failed, $? only tells it when the prompt is drawn
+if (-not $global:_shaiPrompt) {
+    $global:_shaiPrompt = $function:prompt
+    function global:prompt {
+        $global:_shaiLastSucceeded = $?
+        & $global:_shaiPrompt
+    }
+}
+
+Set-PSReadlineKeyHandler -Key '{{fix_key}}' -ScriptBlock {
+    # The last command and the exit status of the last native program. There is no shai-capture
+    # in PowerShell, so the error output is not sent
+    $lastCommand = Get-History -Count 1
+    if (-not $lastCommand -or $global:_shaiLastSucceeded -ne $false) { return }
+    $statusArgs = if ($global:LASTEXITCODE) { "--status $global:LASTEXITCODE" } else { "" }
+    # Generate a unique temporary file name
+    $tempFileName = [System.IO.Path]::GetTempFileName()
+
+    $bufferState = $cursorState = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref] $bufferState, [ref] $cursorState)
+    # Write the buffer content to the temporary file
+    $bufferState | Out-File -FilePath $tempFileName -Force
+    # Run the CLI application with the failed command as a single argument
+    $command = $lastCommand.CommandLine -replace '"', '\"'
+    Start-Process shai -ArgumentList "{{fix_args}} --edit-file $tempFileName --command `"$command`" $statusArgs" -Wait
+    $fileContents = Get-Content -Raw -Path $tempFileName
+    # # Remove the temporary file
+    Remove-Item -Path $tempFileName -Force
+    [Microsoft.PowerShell.PSConsoleReadLine]::BackwardKillLine()
+    [Microsoft.PowerShell.PSConsoleReadLine]::KillLine()
+    [Microsoft.PowerShell.PSConsoleReadLine]::Insert($fileContents)
+}
@@ -11,27 +11,12 @@ use futures_util::StreamExt;
 
 use crate::build_context_request;
 use crate::context::Context;
+use crate::error::{ErrorKind, Provider};
 use crate::model::Task;
-use crate::prompts;
-use crate::sse_parser::ModelStream;
+use crate::sse_parser::{ModelStream, SseError, SseEvent, StreamChunk};
+use crate::usage;
 use crate::ModelError;
 
-#[derive(Deserialize)]
-struct Message {
-    #[allow(unused)]
-    role: String,
-    #[allow(unused)]
-    content: String,
-}
-
-#[derive(Deserialize)]
-struct MessageEntry {
-    #[allow(unused)]
-    index: u64,
-    #[allow(unused)]
-    message: Message,
-}
-
 #[derive(Deserialize)]
 struct Response {
     #[allow(unused)]
@@ -85,7 +70,7 @@ pub(crate) enum AnthropicModel {
 }
 
 impl AnthropicModel {
-    fn api_name(&self) -> String {
+    pub(crate) fn api_name(&self) -> String {
         match self {
             Self::ClaudeOpus46 => "claude-opus-4-6".to_string(),
             Self::ClaudeSonnet46 => "claude-sonnet-4-6".to_string(),
@@ -97,6 +82,11 @@ impl AnthropicModel {
             Self::ClaudeOpus41 => "claude-opus-4-1".to_string(),
         }
     }
+
+    /// Tokens the model accepts in a request, response included.
+    pub(crate) const fn context_window(&self) -> usize {
+        200_000
+    }
 }
 
 #[derive(Debug, Error)]
@@ -105,20 +95,102 @@ pub(crate) enum AnthropicError {
     Authentication(String),
     #[error("Client failed to initialize: {0}")]
     Client(#[from] reqwest::Error),
+    #[error("Request failed: {0}")]
+    Request(reqwest::Error),
     #[error("Stream was interrupted: {0}")]
     Stream(String),
-    #[error("Error Response: {0}")]
-    ErrorResponse(String),
+    #[error("Error Response ({status}): {body}")]
+    ErrorResponse {
+        status: u16,
+        request_id: Option<String>,
+        body: AnthropicErrorResponse,
+    },
     #[error("An unknown error happened: {0}")]
     Unknown(String),
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct AnthropicErrorResponse {
+    error: AnthropicErrorResponseContent,
+}
+
+impl std::fmt::Display for AnthropicErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error.message)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicErrorResponseContent {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    message: String,
+}
+
+impl AnthropicError {
+    /// Build an `ErrorResponse` from a non-successful HTTP response. Bodies that are not the
+    /// usual Anthropic error object (e.g. from a proxy) are kept verbatim as the message.
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let request_id = response
+            .headers()
+            .get("request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(ToString::to_string);
+        let text = response.text().await.unwrap_or_default();
+        let body = serde_json::from_str(&text).unwrap_or(AnthropicErrorResponse {
+            error: AnthropicErrorResponseContent {
+                error_type: None,
+                message: text,
+            },
+        });
+        Self::ErrorResponse {
+            status,
+            request_id,
+            body,
+        }
+    }
+}
+
+impl From<AnthropicError> for ModelError {
+    fn from(err: AnthropicError) -> Self {
+        let provider = Provider::Anthropic;
+        match err {
+            AnthropicError::Authentication(message) => {
+                Self::new(ErrorKind::Authentication, provider, message)
+            }
+            AnthropicError::Client(err) | AnthropicError::Request(err) => {
+                Self::from_reqwest(provider, &err.without_url())
+            }
+            AnthropicError::Stream(message) => {
+                Self::new(ErrorKind::StreamInterrupted, provider, message)
+            }
+            AnthropicError::ErrorResponse {
+                status,
+                request_id,
+                body,
+            } => {
+                let kind = ErrorKind::classify(
+                    Some(status),
+                    body.error.error_type.as_deref(),
+                    &body.error.message,
+                );
+                Self::new(kind, provider, body.error.message)
+                    .with_status(status)
+                    .with_request_id(request_id)
+            }
+            AnthropicError::Unknown(message) => Self::new(ErrorKind::Unknown, provider, message),
+        }
+    }
+}
+
 impl AnthropicModel {
     async fn send_request(
         &self,
         request: String,
         context: Context,
         task: Task,
+        partial_response: Option<String>,
         streaming: bool,
     ) -> Result<reqwest::Response, AnthropicError> {
         let client: Client = ClientBuilder::new()
@@ -126,7 +198,7 @@ impl AnthropicModel {
             .build()
             .map_err(AnthropicError::Client)?;
 
-        let url = "https://api.anthropic.com/v1/messages";
+        let url = Provider::Anthropic.api_url();
         let api_key = std::env::var("ANTHROPIC_API_KEY").map_err(|_| {
             AnthropicError::Authentication(
                 "You need to set ANTHROPIC_API_KEY env variable to use this model".to_string(),
@@ -156,12 +228,9 @@ impl AnthropicModel {
 
         let context_request = build_context_request(&request, context);
 
-        let system_content = match task {
-            Task::GenerateCommand => prompts::ASK_MODEL_TASK,
-            Task::Explain => prompts::EXPLAIN_MODEL_TASK,
-        };
+        let system_content = task.system_prompt();
 
-        let body = json!({
+        let mut body = json!({
             "model": self.api_name(),
             "messages": [
                 {"role": "user", "content": format!("{system_content}\n\n{context_request}")}
@@ -169,6 +238,13 @@ impl AnthropicModel {
             "max_tokens": 1024,
             "stream": streaming,
         });
+        // prefilling the assistant turn makes the model continue from the partial response,
+        // the API rejects a prefill that ends in whitespace
+        if let Some(partial_response) = partial_response {
+            if let Some(messages) = body["messages"].as_array_mut() {
+                messages.push(json!({"role": "assistant", "content": partial_response.trim_end()}));
+            }
+        }
 
         client
             .post(url)
@@ -176,9 +252,7 @@ impl AnthropicModel {
             .json(&body)
             .send()
             .await
-            .map_err(|err| {
-                AnthropicError::Unknown(format!("Unknown request error: {}", err.without_url()))
-            })
+            .map_err(AnthropicError::Request)
     }
 
     pub(crate) async fn send(
@@ -187,15 +261,12 @@ impl AnthropicModel {
         context: Context,
         task: Task,
     ) -> Result<String, AnthropicError> {
-        let response = self.send_request(request, context, task, false).await?;
+        let response = self
+            .send_request(request, context, task, None, false)
+            .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(AnthropicError::ErrorResponse(format!(
-                "API error {}: {}",
-                status, body
-            )));
+            return Err(AnthropicError::from_response(response).await);
         }
 
         let response: Response = response
@@ -230,9 +301,7 @@ struct StreamEvent {
     content_block: Option<ContentBlock>,
     #[allow(unused)]
     delta: Option<Delta>,
-    #[allow(unused)]
     usage: Option<StreamUsage>,
-    #[allow(unused)]
     message: Option<StreamMessage>,
 }
 
@@ -254,12 +323,20 @@ struct Delta {
 
 #[derive(Deserialize)]
 struct StreamUsage {
-    #[allow(unused)]
     input_tokens: Option<u64>,
-    #[allow(unused)]
     output_tokens: Option<u64>,
 }
 
+impl From<StreamUsage> for usage::Usage {
+    fn from(usage: StreamUsage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens.unwrap_or_default(),
+            output_tokens: usage.output_tokens.unwrap_or_default(),
+            reasoning_tokens: 0,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct StreamMessage {
     #[allow(unused)]
@@ -270,6 +347,7 @@ struct StreamMessage {
     role: Option<String>,
     #[allow(unused)]
     content: Option<Vec<StreamContent>>,
+    usage: Option<StreamUsage>,
 }
 
 #[derive(Deserialize)]
@@ -281,24 +359,61 @@ struct StreamContent {
     text: Option<String>,
 }
 
-/// Provider-specific parser for Anthropic SSE data payloads.
+/// Provider-specific parser for Anthropic SSE events.
 /// Extracts text content from streaming message events.
-fn parse_anthropic_message(json_str: &str) -> Result<Vec<String>, String> {
-    let event: StreamEvent =
-        serde_json::from_str(json_str).map_err(|e| format!("Anthropic JSON parse error: {e}"))?;
-    if let Some(delta) = event.delta {
-        if let Some(text) = delta.text {
-            if !text.is_empty() {
-                return Ok(vec![text]);
-            }
+fn parse_anthropic_message(sse_event: &SseEvent) -> Result<Vec<StreamChunk>, ModelError> {
+    match sse_event.event_type() {
+        "message_stop" => return Ok(vec![StreamChunk::Done]),
+        "ping" => return Ok(vec![]),
+        // e.g. an `overloaded_error` in the middle of a stream that started with a 200 status
+        "error" => {
+            let error: AnthropicErrorResponse =
+                serde_json::from_str(&sse_event.data).map_err(|e| {
+                    ModelError::new(
+                        ErrorKind::InvalidResponse,
+                        Provider::Anthropic,
+                        format!("Anthropic JSON parse error: {e}"),
+                    )
+                })?;
+            let kind = ErrorKind::classify(
+                None,
+                error.error.error_type.as_deref(),
+                &error.error.message,
+            );
+            return Err(ModelError::new(
+                kind,
+                Provider::Anthropic,
+                error.error.message,
+            ));
         }
+        _ => (),
     }
-    Ok(vec![])
+    let event: StreamEvent = serde_json::from_str(&sse_event.data).map_err(|e| {
+        ModelError::new(
+            ErrorKind::InvalidResponse,
+            Provider::Anthropic,
+            format!("Anthropic JSON parse error: {e}"),
+        )
+    })?;
+    let mut chunks = vec![];
+    if let Some(text) = event.delta.and_then(|delta| delta.text) {
+        if !text.is_empty() {
+            chunks.push(StreamChunk::Text(text));
+        }
+    }
+    // input tokens come with `message_start`, the final output tokens with `message_delta`
+    let usage = event
+        .usage
+        .or_else(|| event.message.and_then(|message| message.usage));
+    if let Some(usage) = usage {
+        chunks.push(StreamChunk::Usage(usage.into()));
+    }
+    Ok(chunks)
 }
 
-impl From<String> for AnthropicError {
-    fn from(s: String) -> Self {
-        Self::Stream(s)
+impl From<SseError> for AnthropicError {
+    fn from(err: SseError) -> Self {
+        Self::Stream(err.to_string())
     }
 }
 
@@ -308,26 +423,27 @@ impl AnthropicModel {
         request: String,
         context: Context,
         task: Task,
+        partial_response: Option<String>,
     ) -> Result<ModelStream<ModelError>, AnthropicError> {
-        let response = self.send_request(request, context, task, true).await?;
+        let response = self
+            .send_request(request, context, task, partial_response, true)
+            .await?;
         if response.status() == StatusCode::OK {
             let byte_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, String>> + Send>> =
                 Box::pin(response.bytes_stream().map(|r| r.map_err(|e| e.to_string())));
-            let err_map: fn(String) -> ModelError = |s| ModelError::Error(s);
+            let err_map: fn(SseError) -> ModelError = |err| AnthropicError::from(err).into();
             Ok(ModelStream::new(byte_stream, parse_anthropic_message, err_map))
         } else {
-            let body = response.text().await.unwrap_or_default();
-            Err(AnthropicError::ErrorResponse(format!(
-                "API error: {}",
-                body
-            )))
+            Err(AnthropicError::from_response(response).await)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ResponseContent;
+    use super::{parse_anthropic_message, ResponseContent};
+    use crate::error::ErrorKind;
+    use crate::sse_parser::{SseEvent, StreamChunk};
 
     #[test]
     fn text_content_deserialization() {
@@ -339,6 +455,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mid_stream_error_event() {
+        let event = SseEvent {
+            event: Some("error".to_string()),
+            data: r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+                .to_string(),
+            id: None,
+        };
+        let error = parse_anthropic_message(&event).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::Server);
+        assert_eq!(error.message, "Overloaded");
+    }
+
+    #[test]
+    fn message_stop_ends_stream() {
+        let event = SseEvent {
+            event: Some("message_stop".to_string()),
+            data: r#"{"type":"message_stop"}"#.to_string(),
+            id: None,
+        };
+        let chunks = parse_anthropic_message(&event).unwrap();
+        assert!(matches!(chunks.as_slice(), [StreamChunk::Done]));
+    }
+
     /// Integration tests that make real Anthropic API calls.
     /// Requires the `live-api-tests` feature and a valid `ANTHROPIC_API_KEY` env var.
     ///
@@ -348,6 +488,7 @@ mod tests {
         use super::super::AnthropicModel;
         use crate::context::Context;
         use crate::model::Task;
+        use crate::sse_parser::StreamChunk;
         use crate::{AskConfig, ConfigKind};
         use futures_util::StreamExt;
 
@@ -382,7 +523,12 @@ mod tests {
             dotenvy::dotenv().ok();
             let name = model.api_name();
             let stream = model
-                .send_streaming(PROMPT.to_string(), default_context(), Task::GenerateCommand)
+                .send_streaming(
+                    PROMPT.to_string(),
+                    default_context(),
+                    Task::GenerateCommand,
+                    None,
+                )
                 .await;
             assert!(
                 stream.is_ok(),
@@ -394,7 +540,10 @@ mod tests {
                 .collect::<Vec<_>>()
                 .await
                 .into_iter()
-                .map(|chunk| chunk.expect("stream chunk error"))
+                .filter_map(|chunk| match chunk.expect("stream chunk error") {
+                    StreamChunk::Text(text) => Some(text),
+                    _ => None,
+                })
                 .collect();
             assert!(
                 !response.is_empty(),
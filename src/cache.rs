@@ -0,0 +1,191 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, CacheConfig};
+use crate::sse_parser::StreamChunk;
+use crate::ModelError;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// Seconds since the UNIX epoch.
+    created: u64,
+    model: String,
+    response: String,
+}
+
+/// On-disk cache of model responses, one JSON file per response.
+pub(crate) struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+impl ResponseCache {
+    /// The cache in the user's cache directory, `None` if it is disabled.
+    pub(crate) fn open_default(config: &CacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        config::cache_dir().map(|dir| Self::new(dir.join("responses"), config))
+    }
+
+    fn new(dir: PathBuf, config: &CacheConfig) -> Self {
+        Self {
+            dir,
+            ttl: config.ttl,
+            max_entries: config.max_entries,
+        }
+    }
+
+    /// Key identifying a request. Everything that can change the response is part of it.
+    pub(crate) fn key(model: &str, system_prompt: &str, context: &str, request: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [model, system_prompt, context, request] {
+            // length prefix so that moving text between parts changes the key
+            hasher.update(part.len().to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// The cached response for `key`, expired entries are removed.
+    pub(crate) fn get(&self, key: &str) -> Option<String> {
+        self.get_at(key, now())
+    }
+
+    /// [`Self::get`] at `now` seconds since the UNIX epoch.
+    fn get_at(&self, key: &str, now: u64) -> Option<String> {
+        let path = self.path(key);
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if now.saturating_sub(entry.created) > self.ttl.as_secs() {
+            fs::remove_file(path).ok();
+            return None;
+        }
+        Some(entry.response)
+    }
+
+    pub(crate) fn put(&self, key: &str, model: &str, response: &str) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            created: now(),
+            model: model.to_string(),
+            response: response.to_string(),
+        };
+        let text = serde_json::to_string(&entry).map_err(std::io::Error::other)?;
+        fs::write(self.path(key), text)?;
+        self.evict()
+    }
+
+    /// Remove the oldest entries until at most `max_entries` are left.
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries: Vec<(SystemTime, PathBuf)> = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+        if entries.len() <= self.max_entries {
+            return Ok(());
+        }
+        entries.sort();
+        let excess = entries.len() - self.max_entries;
+        for (_, path) in entries.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replay a cached response as a stream, so it goes through the same rendering path as a live
+/// one.
+pub(crate) fn replay(response: String) -> impl Stream<Item = Result<StreamChunk, ModelError>> {
+    let chunks: Vec<Result<StreamChunk, ModelError>> = response
+        .split_inclusive(char::is_whitespace)
+        .map(|word| Ok(StreamChunk::Text(word.to_string())))
+        .collect();
+    futures::stream::iter(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::{now, ResponseCache};
+    use crate::config::CacheConfig;
+
+    /// A cache in a new directory, removed when the `TempDir` is dropped.
+    fn cache(ttl: Duration, max_entries: usize) -> (TempDir, ResponseCache) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(
+            dir.path().to_path_buf(),
+            &CacheConfig {
+                enabled: true,
+                ttl,
+                max_entries,
+            },
+        );
+        (dir, cache)
+    }
+
+    #[test]
+    fn key_depends_on_every_part() {
+        let key = ResponseCache::key("gpt-4o", "system", "context", "list files");
+        assert_eq!(
+            key,
+            ResponseCache::key("gpt-4o", "system", "context", "list files")
+        );
+        assert_ne!(
+            key,
+            ResponseCache::key("gpt-4.1", "system", "context", "list files")
+        );
+        assert_ne!(
+            key,
+            ResponseCache::key("gpt-4o", "system", "contex", "tlist files")
+        );
+    }
+
+    #[test]
+    fn roundtrip_and_expiry() {
+        let (_dir, cache) = cache(Duration::from_secs(60), 10);
+        cache.put("key", "gpt-4o", "ls -la").unwrap();
+        assert_eq!(cache.get("key").as_deref(), Some("ls -la"));
+        assert_eq!(cache.get("other"), None);
+        assert_eq!(cache.get_at("key", now() + 60).as_deref(), Some("ls -la"));
+        assert_eq!(cache.get_at("key", now() + 61), None);
+        // expired entries are removed
+        assert_eq!(cache.get("key"), None);
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let (_dir, cache) = cache(Duration::from_secs(60), 2);
+        for key in ["a", "b", "c"] {
+            cache.put(key, "gpt-4o", key).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c").as_deref(), Some("c"));
+    }
+}
@@ -1,9 +1,10 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::fs;
-use std::io::{self, StdoutLock};
+use std::io::{self, IsTerminal, StdoutLock};
 use std::time::Duration;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, ValueEnum};
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -14,16 +15,35 @@ use crossterm::terminal::{
 use futures::{Stream, StreamExt};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Alignment, Constraint, Direction};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
-use ratatui::Terminal;
+use ratatui::{Terminal, TerminalOptions, Viewport};
 use tui_input::backend::crossterm::EventHandler;
 use tui_input::Input;
 
 use crate::anthropic::AnthropicModel;
-use crate::context::Context;
+use crate::cache::{self, ResponseCache};
+use crate::config::{self, ContextConfig, FileConfig};
+use crate::context::attachment;
+use crate::context::detect::Environment;
+use crate::context::{estimate_tokens, Context, Section};
+use crate::doctor;
+use crate::failure::FailedCommand;
+use crate::insertion::Insertion;
+use crate::install::{self, Installation};
+use crate::integration::{self, KeyBinding, ScriptOptions, Shell};
 use crate::model::Task;
 use crate::openai::OpenAIGPTModel;
-use crate::{model_stream_request, AskConfig, ConfigKind, ExplainConfig, ModelError, ModelKind};
+use crate::prompts;
+use crate::redact::Redactor;
+use crate::sse_parser::StreamChunk;
+use crate::usage::{self, Ledger, LedgerRecord, Usage};
+use crate::viewport::{self, Height};
+use crate::{
+    build_context_request, model_stream_request, task_request, AskConfig, ConfigKind,
+    ExplainConfig, ModelError, ModelKind,
+};
 
 #[derive(Parser, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -34,27 +54,65 @@ pub enum ShaiCLIArgs {
     /// Explain a command
     #[command(arg_required_else_help = true)]
     Explain(ExplainArgs),
+    /// Diagnose a command that failed and propose a corrected one
+    #[command(arg_required_else_help = true)]
+    Fix(FixArgs),
+    /// Explain the output of a command, piped into shai or attached with --file
+    #[command(arg_required_else_help = true)]
+    Analyze(AnalyzeArgs),
+    /// Translate a command to another shell
+    #[command(arg_required_else_help = true)]
+    Translate(TranslateArgs),
     /// Write to stdout the shell integration code for the provided shell
     #[command(arg_required_else_help = true)]
     GenerateScript(IntegrationScriptArgs),
+    /// Install the shell integration and source it from the rc file of your shell(s)
+    Install(InstallArgs),
+    /// Remove the shell integration added by `shai install`
+    Uninstall(UninstallArgs),
+    /// Report the tokens used and their cost
+    Usage(UsageArgs),
+    /// Check the setup of shai and suggest fixes for any problem found
+    Doctor,
 }
 
 #[derive(clap::Args, Clone)]
 #[command(author, about, long_about = None)]
 pub struct AskArgs {
     /// Tell the model which OS should be assumed. Distro names are also valid.
-    #[arg(long, default_value = "Linux")]
-    operating_system: String,
+    /// Defaults to Linux, or to the detected distro with --detect
+    #[arg(long)]
+    operating_system: Option<String>,
 
     /// Tell the model which Shell shold be assumed. This can make the model use shell specific
-    /// features
-    #[arg(long, default_value = "Bash")]
-    shell: String,
+    /// features. Defaults to Bash, or to the detected shell with --detect
+    #[arg(long)]
+    shell: Option<String>,
+
+    /// Detect the distro, the shell and the relevant programs installed (fd, rg, jq, docker or
+    /// podman, the package manager...) instead of assuming them
+    #[arg(long)]
+    detect: bool,
 
-    /// Add the name of a defined environment variable. Repeat to list several items
+    /// Add the name of a defined environment variable, or a glob like `AWS_*` for all the defined
+    /// ones that match. Repeat to list several items
     #[arg(long, short, default_value = None)]
     environment: Option<Vec<String>>,
 
+    /// Also send the value of this environment variable, or of the ones matching a glob like
+    /// `AWS_*`. Values that look secret are masked. Repeat to allow several
+    #[arg(long)]
+    environment_value: Option<Vec<String>>,
+
+    /// Print the environment variables exactly as they would be sent to the model and exit
+    #[arg(long)]
+    preview_environment: bool,
+
+    /// Print the system prompt and the request exactly as they would be sent to the model, with
+    /// a token estimate per context section, and exit
+    #[arg(long)]
+    show_context: bool,
+
     /// Add a program to the list of available programs. Repeat to list several items.
     /// If unset the model is free to use any program
     #[arg(long, short, default_value = None)]
@@ -71,6 +129,54 @@ pub struct AskArgs {
     #[arg(long, default_value=None)]
     depth: Option<u32>,
 
+    /// Provide the model with the last commands of the shell history, up to this many.
+    /// Secrets are redacted like in the rest of the request
+    #[arg(long)]
+    history: Option<usize>,
+
+    /// The history file read by --history, instead of $HISTFILE. The bash and zsh integrations
+    /// pass it since the shells don't export HISTFILE
+    #[arg(long)]
+    history_file: Option<String>,
+
+    /// Provide the model with the installed version of the programs involved (e.g. GNU or BSD
+    /// sed), so it only uses flags they support. Versions are cached until the program changes
+    #[arg(long)]
+    versions: bool,
+
+    /// Provide the model with the state of the git repository of the current directory: branch,
+    /// upstream, changed files, remotes, recent commits and stashes
+    #[arg(long)]
+    git: bool,
+
+    /// Provide the model with the targets, scripts and services of the project manifests
+    /// (Cargo.toml, package.json, Makefile, justfile, pyproject.toml, compose files, flake.nix) in
+    /// the current directory and its parents
+    #[arg(long)]
+    project: bool,
+
+    /// Provide the model with the current kubectl context and namespace
+    #[arg(long)]
+    kubernetes: bool,
+
+    /// Provide the model with the docker or podman context and the names of the running
+    /// containers
+    #[arg(long)]
+    containers: bool,
+
+    /// Provide the model with the hosts configured in ~/.ssh/config
+    #[arg(long)]
+    ssh_hosts: bool,
+
+    /// Attach a sample of this file, its first and last lines. Repeat to attach several. Input
+    /// piped into shai is attached the same way
+    #[arg(long)]
+    file: Option<Vec<std::path::PathBuf>>,
+
+    /// What was piped into shai, sampled
+    #[arg(skip)]
+    stdin: Option<String>,
+
     #[arg(long, value_enum)]
     model: ArgModelKind,
 
@@ -82,23 +188,56 @@ pub struct AskArgs {
     /// model response
     #[arg(long)]
     edit_file: Option<std::path::PathBuf>,
+
+    /// Position of the cursor in the edit file, in characters. shai then generates only the text
+    /// to insert at the cursor, and writes the new cursor position to <EDIT_FILE>.json
+    #[arg(long, requires = "edit_file")]
+    cursor: Option<usize>,
+
+    /// Always send the request to the model, even if the response is cached
+    #[arg(long)]
+    no_cache: bool,
+
+    #[command(flatten)]
+    display: DisplayArgs,
 }
 
 #[derive(clap::Args, Clone)]
 #[command(author, about, long_about = None)]
 pub struct ExplainArgs {
     /// Tell the model which OS should be assumed. Distro names are also valid.
-    #[arg(long, default_value = "Linux")]
-    operating_system: String,
+    /// Defaults to Linux, or to the detected distro with --detect
+    #[arg(long)]
+    operating_system: Option<String>,
 
     /// Tell the model which Shell shold be assumed. This can make the model use shell specific
-    /// features
-    #[arg(long, default_value = "Bash")]
-    shell: String,
+    /// features. Defaults to Bash, or to the detected shell with --detect
+    #[arg(long)]
+    shell: Option<String>,
 
-    /// Add the name of a defined environment variable. Repeat to list several items
+    /// Detect the distro, the shell and the relevant programs installed (fd, rg, jq, docker or
+    /// podman, the package manager...) instead of assuming them
+    #[arg(long)]
+    detect: bool,
+
+    /// Add the name of a defined environment variable, or a glob like `AWS_*` for all the defined
+    /// ones that match. Repeat to list several items
     #[arg(long, default_value = None)]
     environment: Option<Vec<String>>,
+
+    /// Also send the value of this environment variable, or of the ones matching a glob like
+    /// `AWS_*`. Values that look secret are masked. Repeat to allow several
+    #[arg(long)]
+    environment_value: Option<Vec<String>>,
+
+    /// Print the environment variables exactly as they would be sent to the model and exit
+    #[arg(long)]
+    preview_environment: bool,
+
+    /// Print the system prompt and the request exactly as they would be sent to the model, with
+    /// a token estimate per context section, and exit
+    #[arg(long)]
+    show_context: bool,
     ///
     /// Provide the model with the current working directory.
     /// If unset the model does not get any information about what the current directory is
@@ -111,6 +250,54 @@ pub struct ExplainArgs {
     #[arg(long, default_value=None)]
     depth: Option<u32>,
 
+    /// Provide the model with the last commands of the shell history, up to this many.
+    /// Secrets are redacted like in the rest of the request
+    #[arg(long)]
+    history: Option<usize>,
+
+    /// The history file read by --history, instead of $HISTFILE. The bash and zsh integrations
+    /// pass it since the shells don't export HISTFILE
+    #[arg(long)]
+    history_file: Option<String>,
+
+    /// Provide the model with the installed version of the programs involved (e.g. GNU or BSD
+    /// sed), so it only uses flags they support. Versions are cached until the program changes
+    #[arg(long)]
+    versions: bool,
+
+    /// Provide the model with the state of the git repository of the current directory: branch,
+    /// upstream, changed files, remotes, recent commits and stashes
+    #[arg(long)]
+    git: bool,
+
+    /// Provide the model with the targets, scripts and services of the project manifests
+    /// (Cargo.toml, package.json, Makefile, justfile, pyproject.toml, compose files, flake.nix) in
+    /// the current directory and its parents
+    #[arg(long)]
+    project: bool,
+
+    /// Provide the model with the current kubectl context and namespace
+    #[arg(long)]
+    kubernetes: bool,
+
+    /// Provide the model with the docker or podman context and the names of the running
+    /// containers
+    #[arg(long)]
+    containers: bool,
+
+    /// Provide the model with the hosts configured in ~/.ssh/config
+    #[arg(long)]
+    ssh_hosts: bool,
+
+    /// Attach a sample of this file, its first and last lines. Repeat to attach several. Input
+    /// piped into shai is attached the same way
+    #[arg(long)]
+    file: Option<Vec<std::path::PathBuf>>,
+
+    /// What was piped into shai, sampled
+    #[arg(skip)]
+    stdin: Option<String>,
+
     #[arg(long, value_enum)]
     model: ArgModelKind,
 
@@ -121,6 +308,69 @@ pub struct ExplainArgs {
     /// Edit file from which to retrieve the state of ther buffer line
     #[arg(long)]
     edit_file: Option<std::path::PathBuf>,
+
+    /// Always send the request to the model, even if the response is cached
+    #[arg(long)]
+    no_cache: bool,
+
+    #[command(flatten)]
+    display: DisplayArgs,
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct FixArgs {
+    /// The command line that failed
+    #[arg(long)]
+    command: String,
+
+    /// Exit status of the command that failed
+    #[arg(long, allow_hyphen_values = true)]
+    status: Option<i32>,
+
+    /// File with the error output of the command that failed, only its last lines are sent to
+    /// the model. A missing file is ignored
+    #[arg(long)]
+    stderr_file: Option<std::path::PathBuf>,
+
+    #[command(flatten)]
+    ask: AskArgs,
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    ask: AskArgs,
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct TranslateArgs {
+    /// Shell the command is written for. If unset the model recognizes it
+    #[arg(long, value_enum)]
+    from: Option<Shell>,
+
+    /// Shell to translate the command to, it replaces --shell
+    #[arg(long, value_enum)]
+    to: Shell,
+
+    #[command(flatten)]
+    ask: AskArgs,
+}
+
+/// Where the interface is drawn.
+#[derive(clap::Args, Clone)]
+pub struct DisplayArgs {
+    /// Draw the interface below the prompt with this height, in lines (e.g. 20) or as a
+    /// percentage of the terminal (e.g. 40%), instead of taking over the whole screen
+    #[arg(long)]
+    height: Option<Height>,
+
+    /// When running inside tmux, open shai in a popup. The result is passed back through the
+    /// edit file
+    #[arg(long, requires = "edit_file")]
+    tmux_popup: bool,
 }
 
 #[derive(clap::Args, Clone)]
@@ -128,12 +378,210 @@ pub struct ExplainArgs {
 pub struct IntegrationScriptArgs {
     #[arg(long, value_enum)]
     shell: Shell,
+
+    #[command(flatten)]
+    script: ScriptArgs,
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct InstallArgs {
+    /// Shell to install the integration for. Repeat to list several shells.
+    /// If unset your login shell and every shell with an rc file are used
+    #[arg(long, value_enum)]
+    shell: Vec<Shell>,
+
+    /// Show the changes without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    script: ScriptArgs,
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct UninstallArgs {
+    /// Shell to remove the integration from. Repeat to list several shells.
+    /// If unset it is removed from every shell where it is installed
+    #[arg(long, value_enum)]
+    shell: Vec<Shell>,
+
+    /// Show the changes without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Options of the generated integration scripts.
+#[derive(clap::Args, Clone)]
+pub struct ScriptArgs {
+    /// Model used by the generated key bindings
+    #[arg(long, value_enum, default_value = "open-aigpt4o-mini")]
+    model: ArgModelKind,
+
+    /// Key that opens shai to generate a command, e.g. alt-s or ctrl-g
+    #[arg(long, default_value = "alt-s")]
+    ask_key: KeyBinding,
+
+    /// Key that opens shai to explain the current command, e.g. alt-e or ctrl-x
+    #[arg(long, default_value = "alt-e")]
+    explain_key: KeyBinding,
+
+    /// Key that opens shai to insert a command fragment at the cursor, e.g. alt-i
+    #[arg(long, default_value = "alt-i")]
+    insert_key: KeyBinding,
+
+    /// Key that opens shai to fix the last command that failed, e.g. alt-k
+    #[arg(long, default_value = "alt-k")]
+    fix_key: KeyBinding,
+
+    /// Tell the model which OS should be assumed. Distro names are also valid.
+    /// Defaults to Windows for PowerShell and Linux otherwise, or to the detected distro with
+    /// --detect
+    #[arg(long)]
+    os: Option<String>,
+
+    /// Let shai detect the distro and the relevant programs installed on every invocation
+    #[arg(long)]
+    detect: bool,
+
+    /// Additional flags passed to every shai invocation, e.g. "--cwd --depth 2". A flag is left
+    /// out of the invocations that don't accept it, like --program for explain
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    extra_flags: String,
+}
+
+/// Subcommands the integration scripts invoke.
+const INTEGRATION_SUBCOMMANDS: [&str; 3] = ["ask", "explain", "fix"];
+
+/// Whether the shai `subcommand` has the option `flag`, like `--depth`, `--depth=2` or `-e`.
+fn accepts_flag(subcommand: &str, flag: &str) -> bool {
+    let Some(command) = ShaiCLIArgs::command().find_subcommand(subcommand).cloned() else {
+        return false;
+    };
+    let mut arguments = command.get_arguments();
+    match flag.strip_prefix("--") {
+        Some(long) => {
+            let long = long.split('=').next().unwrap_or(long);
+            arguments.any(|argument| argument.get_long() == Some(long))
+        }
+        None => {
+            let short = flag.chars().nth(1);
+            arguments.any(|argument| short.is_some() && argument.get_short() == short)
+        }
+    }
+}
+
+/// The flags of `extra_flags`, with their values, that the shai `subcommand` accepts. A flag that
+/// none of the integration subcommands accepts is kept, so validating the invocation reports it.
+fn extra_flags_for(subcommand: &str, extra_flags: &[String]) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut keep = true;
+    for arg in extra_flags {
+        if arg.starts_with('-') {
+            keep = accepts_flag(subcommand, arg)
+                || !INTEGRATION_SUBCOMMANDS
+                    .iter()
+                    .any(|other| accepts_flag(other, arg));
+        }
+        if keep {
+            flags.push(arg.clone());
+        }
+    }
+    flags
+}
+
+impl ScriptArgs {
+    /// Options to render the script of `shell` with, validated against the command line of shai
+    /// itself.
+    fn script_options(&self, shell: Shell) -> Result<ScriptOptions, String> {
+        let model = self
+            .model
+            .to_possible_value()
+            .expect("models are not skipped");
+        let options = ScriptOptions {
+            shell,
+            model: model.get_name().to_string(),
+            operating_system: match (&self.os, self.detect) {
+                (Some(os), _) => Some(os.clone()),
+                (None, true) => None,
+                (None, false) => Some(shell.default_operating_system().to_string()),
+            },
+            detect: self.detect,
+            ask_key: self.ask_key,
+            explain_key: self.explain_key,
+            insert_key: self.insert_key,
+            fix_key: self.fix_key,
+            extra_flags: {
+                let extra_flags = integration::split_args(&self.extra_flags)?;
+                INTEGRATION_SUBCOMMANDS
+                    .iter()
+                    .map(|subcommand| {
+                        (
+                            subcommand.to_string(),
+                            extra_flags_for(subcommand, &extra_flags),
+                        )
+                    })
+                    .collect()
+            },
+        };
+        for subcommand in INTEGRATION_SUBCOMMANDS {
+            let args = options.shai_args(subcommand);
+            // the scripts pass the failed command to fix themselves
+            let failure: &[&str] = if subcommand == "fix" {
+                &["--command", "false"]
+            } else {
+                &[]
+            };
+            ShaiCLIArgs::try_parse_from(
+                std::iter::once("shai".to_string())
+                    .chain(args)
+                    .chain(["--edit-file".to_string(), "buffer".to_string()])
+                    .chain(failure.iter().map(ToString::to_string)),
+            )
+            .map_err(|err| format!("Invalid flags for shai {subcommand}:\n{err}"))?;
+        }
+        Ok(options)
+    }
+}
+
+#[derive(clap::Args, Clone)]
+#[command(author, about, long_about = None)]
+pub struct UsageArgs {
+    /// Only include requests newer than this, e.g. 12h, 30d or 2w.
+    /// If unset all recorded requests are included
+    #[arg(long, default_value = None)]
+    since: Option<String>,
+
+    /// How to group the requests in the report
+    #[arg(long, value_enum, default_value = "model")]
+    by: UsageGrouping,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum UsageGrouping {
+    Model,
+    Provider,
+    Task,
+}
+
+impl UsageGrouping {
+    fn key(&self) -> fn(&LedgerRecord) -> String {
+        match self {
+            Self::Model => |record| record.model.clone(),
+            Self::Provider => |record| record.provider.clone(),
+            Self::Task => |record| record.task.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum ShaiArgs {
     Ask(AskArgs),
     Explain(ExplainArgs),
+    Fix(FixArgs),
+    Analyze(AnalyzeArgs),
+    Translate(TranslateArgs),
 }
 
 impl ShaiArgs {
@@ -141,12 +589,115 @@ impl ShaiArgs {
         match self {
             Self::Ask(args) => &args.edit_file,
             Self::Explain(args) => &args.edit_file,
+            Self::Fix(args) => &args.ask.edit_file,
+            Self::Analyze(args) => &args.ask.edit_file,
+            Self::Translate(args) => &args.ask.edit_file,
         }
     }
     const fn write_stdout(&self) -> bool {
         match self {
             Self::Ask(args) => args.write_stdout,
             Self::Explain(args) => args.write_stdout,
+            Self::Fix(args) => args.ask.write_stdout,
+            Self::Analyze(args) => args.ask.write_stdout,
+            Self::Translate(args) => args.ask.write_stdout,
+        }
+    }
+    const fn cursor(&self) -> Option<usize> {
+        match self {
+            Self::Ask(args) => args.cursor,
+            Self::Explain(_) | Self::Fix(_) | Self::Analyze(_) | Self::Translate(_) => None,
+        }
+    }
+    const fn display(&self) -> &DisplayArgs {
+        match self {
+            Self::Ask(args) => &args.display,
+            Self::Explain(args) => &args.display,
+            Self::Fix(args) => &args.ask.display,
+            Self::Analyze(args) => &args.ask.display,
+            Self::Translate(args) => &args.ask.display,
+        }
+    }
+    const fn preview_environment(&self) -> bool {
+        match self {
+            Self::Ask(args) => args.preview_environment,
+            Self::Explain(args) => args.preview_environment,
+            Self::Fix(args) => args.ask.preview_environment,
+            Self::Analyze(args) => args.ask.preview_environment,
+            Self::Translate(args) => args.ask.preview_environment,
+        }
+    }
+    /// The model of the request, without building its config.
+    fn model(&self) -> ModelKind {
+        match self {
+            Self::Ask(args) => args.model.clone(),
+            Self::Explain(args) => args.model.clone(),
+            Self::Fix(args) => args.ask.model.clone(),
+            Self::Analyze(args) => args.ask.model.clone(),
+            Self::Translate(args) => args.ask.model.clone(),
+        }
+        .into()
+    }
+    fn files(&self) -> &[std::path::PathBuf] {
+        match self {
+            Self::Ask(args) => args.file.as_deref(),
+            Self::Explain(args) => args.file.as_deref(),
+            Self::Fix(args) => args.ask.file.as_deref(),
+            Self::Analyze(args) => args.ask.file.as_deref(),
+            Self::Translate(args) => args.ask.file.as_deref(),
+        }
+        .unwrap_or_default()
+    }
+    /// Turn on the opt-in context sources enabled in the config file, on top of the flags.
+    fn with_context_sources(mut self, config: &ContextConfig) -> Self {
+        let (kubernetes, containers, ssh_hosts) = match &mut self {
+            Self::Ask(args) => (
+                &mut args.kubernetes,
+                &mut args.containers,
+                &mut args.ssh_hosts,
+            ),
+            Self::Explain(args) => (
+                &mut args.kubernetes,
+                &mut args.containers,
+                &mut args.ssh_hosts,
+            ),
+            Self::Fix(FixArgs { ask, .. })
+            | Self::Analyze(AnalyzeArgs { ask })
+            | Self::Translate(TranslateArgs { ask, .. }) => {
+                (&mut ask.kubernetes, &mut ask.containers, &mut ask.ssh_hosts)
+            }
+        };
+        *kubernetes |= config.kubernetes;
+        *containers |= config.containers;
+        *ssh_hosts |= config.ssh_hosts;
+        self
+    }
+    fn with_stdin(mut self, stdin: Option<String>) -> Self {
+        match &mut self {
+            Self::Ask(args) => args.stdin = stdin,
+            Self::Explain(args) => args.stdin = stdin,
+            Self::Fix(args) => args.ask.stdin = stdin,
+            Self::Analyze(args) => args.ask.stdin = stdin,
+            Self::Translate(args) => args.ask.stdin = stdin,
+        }
+        self
+    }
+    const fn show_context(&self) -> bool {
+        match self {
+            Self::Ask(args) => args.show_context,
+            Self::Explain(args) => args.show_context,
+            Self::Fix(args) => args.ask.show_context,
+            Self::Analyze(args) => args.ask.show_context,
+            Self::Translate(args) => args.ask.show_context,
+        }
+    }
+    const fn no_cache(&self) -> bool {
+        match self {
+            Self::Ask(args) => args.no_cache,
+            Self::Explain(args) => args.no_cache,
+            Self::Fix(args) => args.ask.no_cache,
+            Self::Analyze(args) => args.ask.no_cache,
+            Self::Translate(args) => args.ask.no_cache,
         }
     }
 }
@@ -156,6 +707,13 @@ impl From<ShaiArgs> for ConfigKind {
         match value {
             ShaiArgs::Ask(args) => Self::Ask(AskConfig::from(args)),
             ShaiArgs::Explain(args) => Self::Explain(ExplainConfig::from(args)),
+            ShaiArgs::Fix(args) => Self::Ask(AskConfig::from(args.ask)),
+            ShaiArgs::Analyze(args) => Self::Ask(AskConfig::from(args.ask)),
+            // the context describes the shell the command is translated to
+            ShaiArgs::Translate(mut args) => {
+                args.ask.shell = Some(args.to.model_name().to_string());
+                Self::Ask(AskConfig::from(args.ask))
+            }
         }
     }
 }
@@ -217,26 +775,35 @@ impl From<ArgModelKind> for ModelKind {
     }
 }
 
-#[derive(clap::ValueEnum, Clone)]
-enum Shell {
-    Bash,
-    Zsh,
-    Fish,
-    Nushell,
-    PowerShell,
-}
-
 impl From<AskArgs> for AskConfig {
     fn from(value: AskArgs) -> Self {
         let cwd = if value.cwd { Some(()) } else { None };
         let model = value.model.into();
+        let detected = value.detect.then(Environment::detect);
+        let (operating_system, shell, tools) =
+            resolve_environment(value.operating_system, value.shell, detected);
         Self {
-            operating_system: value.operating_system,
-            shell: value.shell,
+            operating_system,
+            shell,
+            tools,
             environment: value.environment,
+            environment_values: value.environment_value,
             programs: value.program,
             cwd,
             depth: value.depth,
+            history: value.history,
+            history_file: value
+                .history_file
+                .filter(|path| !path.is_empty())
+                .map(std::path::PathBuf::from),
+            versions: value.versions.then_some(()),
+            git: value.git.then_some(()),
+            project: value.project.then_some(()),
+            kubernetes: value.kubernetes.then_some(()),
+            containers: value.containers.then_some(()),
+            ssh_hosts: value.ssh_hosts.then_some(()),
+            files: value.file,
+            stdin: value.stdin,
             model,
         }
     }
@@ -246,38 +813,209 @@ impl From<ExplainArgs> for ExplainConfig {
     fn from(value: ExplainArgs) -> Self {
         let cwd = if value.cwd { Some(()) } else { None };
         let model = value.model.into();
+        let detected = value.detect.then(Environment::detect);
+        let (operating_system, shell, tools) =
+            resolve_environment(value.operating_system, value.shell, detected);
         Self {
-            operating_system: value.operating_system,
-            shell: value.shell,
+            operating_system,
+            shell,
+            tools,
             environment: value.environment,
+            environment_values: value.environment_value,
             cwd,
             depth: value.depth,
+            history: value.history,
+            history_file: value
+                .history_file
+                .filter(|path| !path.is_empty())
+                .map(std::path::PathBuf::from),
+            versions: value.versions.then_some(()),
+            git: value.git.then_some(()),
+            project: value.project.then_some(()),
+            kubernetes: value.kubernetes.then_some(()),
+            containers: value.containers.then_some(()),
+            ssh_hosts: value.ssh_hosts.then_some(()),
+            files: value.file,
+            stdin: value.stdin,
             model,
         }
     }
 }
 
+/// The OS, shell and installed programs told to the model: the flags take precedence over what
+/// was detected, and the defaults are used when neither is available.
+fn resolve_environment(
+    operating_system: Option<String>,
+    shell: Option<String>,
+    detected: Option<Environment>,
+) -> (String, String, Option<Vec<String>>) {
+    let detected = detected.unwrap_or_default();
+    let tools = Some(detected.tools).filter(|tools| !tools.is_empty());
+    (
+        operating_system
+            .or(detected.operating_system)
+            .unwrap_or_else(|| "Linux".to_string()),
+        shell
+            .or(detected.shell)
+            .unwrap_or_else(|| "Bash".to_string()),
+        tools,
+    )
+}
+
 #[allow(clippy::missing_errors_doc)]
 pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let args = ShaiCLIArgs::parse();
     match args {
-        ShaiCLIArgs::Ask(shai_args) => {
-            let mut ui = ShaiUI::new(ShaiArgs::Ask(shai_args))?;
-            ui.run().await?;
-        }
-        ShaiCLIArgs::Explain(shai_args) => {
-            let mut ui = ShaiUI::new(ShaiArgs::Explain(shai_args))?;
-            ui.run().await?;
-        }
-        ShaiCLIArgs::GenerateScript(integration_args) => match integration_args.shell {
-            Shell::Bash => println!("{}", include_str!("../scripts//bash_assistant.sh")),
-            Shell::Zsh => println!("{}", include_str!("../scripts/zsh_assistant.zsh")),
-            Shell::Fish => println!("{}", include_str!("../scripts/fish_assistant.fish")),
-            Shell::Nushell => println!("{}", include_str!("../scripts/nushell_assistant.nu")),
-            Shell::PowerShell => {
-                println!("{}", include_str!("../scripts/powershell_assistant.ps1"));
+        ShaiCLIArgs::Ask(shai_args) => run_ui(ShaiArgs::Ask(shai_args)).await?,
+        ShaiCLIArgs::Explain(shai_args) => run_ui(ShaiArgs::Explain(shai_args)).await?,
+        ShaiCLIArgs::Fix(shai_args) => run_ui(ShaiArgs::Fix(shai_args)).await?,
+        ShaiCLIArgs::Analyze(shai_args) => run_ui(ShaiArgs::Analyze(shai_args)).await?,
+        ShaiCLIArgs::Translate(shai_args) => run_ui(ShaiArgs::Translate(shai_args)).await?,
+        ShaiCLIArgs::Usage(usage_args) => print_usage_report(&usage_args)?,
+        ShaiCLIArgs::Doctor => {
+            let checks = doctor::run_checks().await;
+            print!("{}", doctor::report(&checks));
+            if checks
+                .iter()
+                .any(|check| check.status == doctor::Status::Fail)
+            {
+                return Err("shai doctor found problems, see the fixes above".into());
             }
-        },
+        }
+        ShaiCLIArgs::Install(install_args) => install(&install_args)?,
+        ShaiCLIArgs::Uninstall(uninstall_args) => uninstall(&uninstall_args)?,
+        ShaiCLIArgs::GenerateScript(integration_args) => {
+            println!(
+                "{}",
+                integration_args
+                    .script
+                    .script_options(integration_args.shell)?
+                    .render()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A sample of what is piped into shai, `None` when stdin is the terminal.
+fn read_piped_stdin() -> io::Result<Option<String>> {
+    if io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let sampled = attachment::sample(io::stdin().lock())?;
+    Ok(sampled.filter(|text| !text.trim().is_empty()))
+}
+
+async fn run_ui(args: ShaiArgs) -> Result<(), Box<dyn std::error::Error>> {
+    viewport::load_popup_secrets()?;
+    let config = FileConfig::load()?;
+    for file in args.files() {
+        fs::File::open(file).map_err(|err| format!("Can't attach {}: {err}", file.display()))?;
+    }
+    let piped = read_piped_stdin()?;
+    let has_piped_input = piped.is_some();
+    if matches!(args, ShaiArgs::Analyze(_)) && !has_piped_input && args.files().is_empty() {
+        return Err(
+            "Nothing to analyze, pipe the output into `shai analyze` or pass it with --file".into(),
+        );
+    }
+    let args = args.with_stdin(piped).with_context_sources(&config.context);
+    if args.preview_environment() {
+        let mut redactor = Redactor::new(&config.redaction);
+        let context = Context::from(ConfigKind::from(args)).redacted(&mut redactor);
+        print!("{}", context.environment_preview());
+        return Ok(());
+    }
+    if args.show_context() {
+        let mut redactor = Redactor::new(&config.redaction);
+        let (prompt, insertion, failure) = initial_prompt(&args)?;
+        let (task, user_prompt, context) = prepare_request(
+            &args,
+            RequestType::Normal,
+            prompt,
+            insertion.as_ref(),
+            failure.as_ref(),
+            &mut redactor,
+        );
+        let model = ConfigKind::from(args).model().clone();
+        let context = fit_context(&config.context, &model, task, &user_prompt, context);
+        print!("{}", context_report(task, &user_prompt, context));
+        return Ok(());
+    }
+    let display = args.display();
+    // the popup runs a new shai that can't read what was piped into this one
+    if !has_piped_input && viewport::use_tmux_popup(display.tmux_popup) {
+        viewport::run_in_tmux_popup(display.height)?;
+        return Ok(());
+    }
+    let mut ui = ShaiUI::new(args, config)?;
+    ui.run().await
+}
+
+fn install(args: &InstallArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let shells = if args.shell.is_empty() {
+        install::detect_shells()?
+    } else {
+        args.shell.clone()
+    };
+    for shell in shells {
+        let installation = Installation::of(shell)?;
+        let script = args.script.script_options(shell)?.render();
+        let changes = installation.install(&script, args.dry_run)?;
+        if args.dry_run {
+            print!("{changes}");
+        } else if changes.is_empty() {
+            println!("The {shell:?} integration is up to date");
+        } else {
+            println!(
+                "Installed the {shell:?} integration in {}, open a new shell to use it",
+                installation.rc_file.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn uninstall(args: &UninstallArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let shells = if args.shell.is_empty() {
+        Shell::value_variants().to_vec()
+    } else {
+        args.shell.clone()
+    };
+    for shell in shells {
+        let installation = Installation::of(shell)?;
+        if !installation.is_installed() {
+            if !args.shell.is_empty() {
+                println!("The {shell:?} integration is not installed");
+            }
+            continue;
+        }
+        let changes = installation.uninstall(args.dry_run)?;
+        if args.dry_run {
+            print!("{changes}");
+        } else {
+            println!(
+                "Removed the {shell:?} integration from {}",
+                installation.rc_file.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_usage_report(args: &UsageArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = Ledger::open_default().ok_or("Could not determine the data directory")?;
+    let mut records = ledger.read()?;
+    if let Some(since) = &args.since {
+        let since = config::parse_duration(since)?;
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        let cutoff = now.saturating_sub(since).as_secs();
+        records.retain(|record| record.timestamp >= cutoff);
+    }
+    if records.is_empty() {
+        println!("No requests recorded");
+    } else {
+        print!("{}", usage::report(&records, args.by.key()));
     }
     Ok(())
 }
@@ -317,9 +1055,13 @@ enum ShaiState {
     ExplanationGenerated,
     CommandGenerated,
     AuxExplanationGenerated,
+    /// The output was analyzed, a fix can be generated from the analysis.
+    AnalysisGenerated,
+    /// A fix was generated from the analysis of the output.
+    AnalysisFixGenerated,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum RequestType {
     // stdin -> main_response
     Normal,
@@ -350,14 +1092,36 @@ impl Display for ShaiRequestProgress {
     }
 }
 
-fn create_explanation_paragraph<'t>(
-    text: String,
-    thinking: ShaiRequestProgress,
-    focus: bool,
-) -> Paragraph<'t> {
+fn create_explanation_paragraph<'t>(response: &Response, focus: bool) -> Paragraph<'t> {
     let focus_indicator = if focus { "*" } else { "" };
-    let title = format!("Shai {thinking} {focus_indicator}");
-    Paragraph::new(text)
+    let title = if response.error.is_some() {
+        format!("Shai (error) {focus_indicator}")
+    } else if response.cached {
+        format!("Shai (cached) {focus_indicator}")
+    } else {
+        format!("Shai {} {focus_indicator}", response.request_state)
+    };
+    let title = match &response.trim_note {
+        Some(note) => format!("{title}({note}) "),
+        None => title,
+    };
+    let mut content = Text::from(response.text.clone());
+    if let Some(error) = &response.error {
+        let error_style = Style::default().fg(Color::Red);
+        if !content.lines.is_empty() {
+            content.lines.push(Line::default());
+        }
+        content
+            .lines
+            .push(Line::from(Span::styled(error.to_string(), error_style)));
+        if let Some(hint) = error.hint() {
+            content.lines.push(Line::from(Span::styled(
+                format!("Hint: {hint}"),
+                error_style,
+            )));
+        }
+    }
+    Paragraph::new(content)
         .block(Block::default().borders(Borders::ALL).title(title))
         .alignment(Alignment::Left)
         .wrap(Wrap { trim: true })
@@ -369,29 +1133,322 @@ fn create_input_paragraph<'t>(text: String, title: String) -> Paragraph<'t> {
         .alignment(Alignment::Left)
 }
 
-fn create_controls_paragraph<'t>(state: ShaiState) -> Paragraph<'t> {
-    let text = match state {
+fn create_controls_paragraph<'t>(
+    state: ShaiState,
+    resumable: bool,
+    translatable: bool,
+    usage_summary: String,
+) -> Paragraph<'t> {
+    let mut text = match state {
         ShaiState::Started=>  "<C-c>: Exit | Enter: Send Prompt".to_string(),
         ShaiState::Processing => "<C-c>: Exit | Esc: Cancel ".to_string(),
         ShaiState::ExplanationGenerated => "<C-c>: Exit | Enter: Send Prompt | <C-u|d>: Scroll".to_string(),
         ShaiState::CommandGenerated => "<C-c>: Exit | Enter: Send Prompt | <C-a>: Accept | <C-A>: Accept (raw) | <C-e>: Explain".to_string(),
         ShaiState::AuxExplanationGenerated =>"<C-c>: Exit | Enter: Send Prompt | <C-a>: Accept | <C-A>: Accept (raw) | <C-e>: Explain | <Tab>: Toggle Focus | <C-u|d>: Scroll | <S-Up|Down>: Resize explanation".to_string(),
+        ShaiState::AnalysisGenerated => "<C-c>: Exit | Enter: Send Prompt | <C-f>: Fix command | <C-u|d>: Scroll".to_string(),
+        ShaiState::AnalysisFixGenerated => "<C-c>: Exit | Enter: Send Prompt | <C-a>: Accept fix | <C-A>: Accept fix (raw) | <C-f>: Fix command | <Tab>: Toggle Focus | <C-u|d>: Scroll | <S-Up|Down>: Resize fix".to_string(),
     };
+    if resumable && !matches!(state, ShaiState::Processing) {
+        text.push_str(" | <C-o>: Resume");
+    }
+    if translatable && !matches!(state, ShaiState::Processing) {
+        text.push_str(" | <C-t>: Translate");
+    }
+    if !matches!(state, ShaiState::Processing) {
+        text.push_str(" | <C-k>: Context");
+    }
     Paragraph::new(text)
-        .block(Block::default().borders(Borders::TOP))
+        .block(usage_block(usage_summary))
         .alignment(Alignment::Left)
         .wrap(Wrap { trim: true })
 }
 
+/// Top border with the session usage on the right. ratatui fails to draw an empty title aligned
+/// to the right, so it is only set once there is usage to show.
+fn usage_block<'t>(usage_summary: String) -> Block<'t> {
+    let block = Block::default().borders(Borders::TOP);
+    if usage_summary.is_empty() {
+        block
+    } else {
+        block.title(usage_summary).title_alignment(Alignment::Right)
+    }
+}
+
+/// The prompt the interface starts with, and what the request is about besides the prompt.
+fn initial_prompt(
+    args: &ShaiArgs,
+) -> io::Result<(String, Option<Insertion>, Option<FailedCommand>)> {
+    let buffer = args
+        .edit_file()
+        .as_ref()
+        .and_then(|file| fs::read_to_string(file).ok())
+        .unwrap_or_default();
+    // when inserting, the buffer is the command line and the prompt starts empty
+    let (cli_text, insertion) = match args.cursor() {
+        Some(cursor) => {
+            let line = buffer.trim_end_matches(['\n', '\r']);
+            (String::new(), Some(Insertion::at(line, cursor)))
+        }
+        None => (buffer.trim().to_string(), None),
+    };
+    // when fixing, the failure is the request and the prompt is only for extra details
+    let (cli_text, failure) = match args {
+        ShaiArgs::Fix(fix) => (
+            String::new(),
+            Some(FailedCommand::new(
+                fix.command.clone(),
+                fix.status,
+                fix.stderr_file.as_deref(),
+            )?),
+        ),
+        // the output is the request, the buffer line is only written with the fix
+        ShaiArgs::Analyze(_) => (String::new(), None),
+        _ => (cli_text, None),
+    };
+    Ok((cli_text, insertion, failure))
+}
+
+/// The request to translate `command` between shells.
+fn translate_request(from: Option<Shell>, to: Shell, command: &str) -> String {
+    match from {
+        Some(from) => format!(
+            "Translate this {} command to {}:\n{command}",
+            from.model_name(),
+            to.model_name()
+        ),
+        None => format!(
+            "Translate this command, written for another shell, to {}:\n{command}",
+            to.model_name()
+        ),
+    }
+}
+
+/// The task, the prompt and the context of a request, with their secrets redacted.
+fn prepare_request(
+    args: &ShaiArgs,
+    request_type: RequestType,
+    prompt: String,
+    insertion: Option<&Insertion>,
+    failure: Option<&FailedCommand>,
+    redactor: &mut Redactor,
+) -> (Task, String, Context) {
+    let config = ConfigKind::from(args.clone());
+    let task = match config {
+        ConfigKind::Ask(_) if matches!(args, ShaiArgs::Analyze(_)) => match request_type {
+            RequestType::Normal => Task::AnalyzeOutput,
+            RequestType::Auxiliary => Task::GenerateCommand,
+        },
+        ConfigKind::Ask(_) if matches!(args, ShaiArgs::Translate(_)) => match request_type {
+            RequestType::Normal => Task::Translate,
+            RequestType::Auxiliary => Task::Explain,
+        },
+        ConfigKind::Ask(_) => match (request_type, insertion, failure) {
+            (RequestType::Normal, _, Some(_)) => Task::FixCommand,
+            (RequestType::Normal, None, None) => Task::GenerateCommand,
+            (RequestType::Normal, Some(_), None) => Task::InsertFragment,
+            (RequestType::Auxiliary, _, _) => Task::Explain,
+        },
+        ConfigKind::Explain(_) => Task::Explain,
+    };
+    // only the programs of a command line are looked up, the words of a prompt may be anything
+    let command = match task {
+        Task::Explain | Task::Translate => Some(prompt.clone()),
+        Task::FixCommand => failure.map(|failure| failure.command.clone()),
+        _ => None,
+    };
+    let mut context = Context::from(config);
+    let mut user_prompt = prompt;
+    if let (Task::InsertFragment, Some(insertion)) = (task, insertion) {
+        context = context.with_insertion(insertion);
+        if user_prompt.trim().is_empty() {
+            user_prompt = prompts::INSERT_DEFAULT_REQUEST.to_string();
+        }
+    }
+    if let (Task::FixCommand, Some(failure)) = (task, failure) {
+        user_prompt = failure.request(&user_prompt);
+    }
+    if let Task::AnalyzeOutput = task {
+        if user_prompt.trim().is_empty() {
+            user_prompt = prompts::ANALYZE_DEFAULT_REQUEST.to_string();
+        }
+    }
+    if let (ShaiArgs::Translate(translate), Task::Translate) = (args, task) {
+        user_prompt = translate_request(translate.from, translate.to, &user_prompt);
+    }
+    // the fix is generated from the analysis, and the output is still in the context
+    if let (ShaiArgs::Analyze(_), Task::GenerateCommand) = (args, task) {
+        user_prompt = format!("{}\n{user_prompt}", prompts::FIX_FROM_ANALYSIS_REQUEST);
+    }
+    context = context.with_tool_versions(&user_prompt, command.as_deref());
+    // secrets never leave the machine, the placeholders are restored when writing the buffer
+    (
+        task,
+        redactor.redact(&user_prompt),
+        context.redacted(redactor),
+    )
+}
+
+/// Tokens of the model context window kept free for the response.
+const RESPONSE_RESERVE: usize = 4096;
+
+/// `context` trimmed to the budget configured for `model`, and to what is left of its context
+/// window next to the prompts and the response.
+fn fit_context(
+    config: &ContextConfig,
+    model: &ModelKind,
+    task: Task,
+    user_prompt: &str,
+    context: Context,
+) -> Context {
+    let available = model
+        .context_window()
+        .saturating_sub(estimate_tokens(task.system_prompt()))
+        .saturating_sub(estimate_tokens(&task_request(user_prompt)))
+        .saturating_sub(RESPONSE_RESERVE);
+    let budget = config
+        .budget(&model.api_name())
+        .map_or(available, |budget| budget.min(available));
+    context.fit(budget)
+}
+
+/// The system prompt and the request exactly as they are sent, with a token estimate per
+/// context section.
+fn context_report(task: Task, user_prompt: &str, context: Context) -> String {
+    let system_prompt = task.system_prompt();
+    let mut report = format!(
+        "System prompt ({}, ~{} tokens):\n{system_prompt}\n\nContext sections:\n",
+        task.name(),
+        estimate_tokens(system_prompt)
+    );
+    for section in context.sections() {
+        let state = if context.is_enabled(section.name) {
+            ""
+        } else {
+            " (disabled)"
+        };
+        report += &format!(
+            "  {:<20} ~{} tokens{state}\n",
+            section.name,
+            estimate_tokens(&section.text)
+        );
+    }
+    if let Some(note) = context.trim_note() {
+        report += &format!("  ({note})\n");
+    }
+    let request = build_context_request(user_prompt, context);
+    report += &format!(
+        "\nRequest (~{} tokens):\n{request}\n",
+        estimate_tokens(&request)
+    );
+    report
+}
+
+/// A request prepared for a prompt, kept so inspecting and sending it build the context once.
+struct PreparedRequest {
+    request_type: RequestType,
+    prompt: String,
+    task: Task,
+    user_prompt: String,
+    context: Context,
+}
+
+/// What the next request sends, section by section, so sections can be left out.
+struct Inspector {
+    task: Task,
+    /// Tokens of the `<task>` part of the request.
+    request_tokens: usize,
+    sections: Vec<Section>,
+    selected: usize,
+    /// What was trimmed to fit the context budget.
+    trim_note: Option<String>,
+}
+
+fn create_inspector_paragraph<'t>(
+    inspector: &Inspector,
+    disabled: &HashSet<&'static str>,
+) -> Paragraph<'t> {
+    let system_tokens = estimate_tokens(inspector.task.system_prompt());
+    let mut total = system_tokens + inspector.request_tokens;
+    let mut lines = vec![
+        Line::from(format!(
+            "    System prompt ({}) ~{system_tokens} tokens",
+            inspector.task.name()
+        )),
+        Line::from(format!("    Request ~{} tokens", inspector.request_tokens)),
+    ];
+    for (index, section) in inspector.sections.iter().enumerate() {
+        let tokens = estimate_tokens(&section.text);
+        let enabled = !disabled.contains(section.name);
+        if enabled {
+            total += tokens;
+        }
+        let line = format!(
+            "{} [{}] {:<20} ~{tokens} tokens",
+            if index == inspector.selected {
+                ">"
+            } else {
+                " "
+            },
+            if enabled { "x" } else { " " },
+            section.name
+        );
+        lines.push(if index == inspector.selected {
+            Line::styled(line, Style::default().fg(Color::Yellow))
+        } else {
+            Line::from(line)
+        });
+    }
+    lines.push(Line::from(format!("    Total ~{total} tokens")));
+    if let Some(note) = &inspector.trim_note {
+        lines.push(Line::styled(
+            format!("    ({note})"),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    lines.push(Line::from(""));
+    if let Some(section) = inspector.sections.get(inspector.selected) {
+        lines.extend(
+            section
+                .text
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
+    }
+    Paragraph::new(Text::from(lines))
+        .block(Block::default().borders(Borders::ALL).title("Context"))
+        .wrap(Wrap { trim: false })
+}
+
+fn create_inspector_controls_paragraph<'t>(usage_summary: String) -> Paragraph<'t> {
+    Paragraph::new(
+        "<C-c>: Exit | Enter: Send Prompt | <Up|Down>: Select | <Space>: Toggle section | <C-k>|Esc: Close",
+    )
+    .block(usage_block(usage_summary))
+    .alignment(Alignment::Left)
+    .wrap(Wrap { trim: true })
+}
+
 #[derive(Default)]
 struct Response {
     text: String,
+    error: Option<ModelError>,
+    /// The text was replayed from the response cache
+    cached: bool,
+    /// What was trimmed from the context to fit its budget
+    trim_note: Option<String>,
+    /// The text is being continued, a continuation that starts with whitespace replaces the
+    /// whitespace it ends with
+    resuming: bool,
     scroll: u16,
     request_state: ShaiRequestProgress,
 }
 
 pub struct ShaiUI<'t> {
     args: ShaiArgs,
+    config: FileConfig,
+    /// Tokens and cost of all the requests sent in this session
+    session_usage: Usage,
+    session_cost: Option<f64>,
     term: Terminal<CrosstermBackend<StdoutLock<'t>>>,
     layout: Layout,
     input_text: String,
@@ -400,6 +1457,20 @@ pub struct ShaiUI<'t> {
     auxiliary_response: Response,
     main_response_size: u16,
     response_focus: Focus,
+    /// Set when the response is inserted at the cursor of the command line.
+    insertion: Option<Insertion>,
+    /// Set when fixing a command that failed.
+    failure: Option<FailedCommand>,
+    /// Drawn below the prompt instead of on the alternate screen.
+    inline: bool,
+    /// Keeps the secrets of the requests out of what is sent to the provider.
+    redactor: Redactor,
+    /// Context sections left out of the requests, toggled in the inspector.
+    disabled_sections: HashSet<&'static str>,
+    /// Open while inspecting the context.
+    inspector: Option<Inspector>,
+    /// The last request prepared, reused while its prompt doesn't change.
+    prepared: Option<PreparedRequest>,
 }
 
 fn extract_code_blocks(text: &str) -> Vec<String> {
@@ -473,23 +1544,59 @@ fn estimate_text_height(text: &str, width: usize) -> usize {
         .fold(1, |acc, line| acc + div_ceil(line.len().max(1), width))
 }
 
+/// Keys are read from the terminal. When the input is piped into shai stdin is not the
+/// terminal, and crossterm reads the keys and sets raw mode on `/dev/tty` instead, so it has to
+/// be available.
+fn check_terminal_input() -> io::Result<()> {
+    if io::stdin().is_terminal() || cfg!(windows) {
+        return Ok(());
+    }
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map(drop)
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("stdin is not a terminal and /dev/tty can't be opened to read keys: {err}"),
+            )
+        })
+}
+
 impl<'t> ShaiUI<'t> {
     /// This function initializes Shai and eases disabling terminal raw mode in all circumstances
-    fn initialization(args: ShaiArgs) -> Result<Self, Box<dyn std::error::Error>> {
+    fn initialization(
+        args: ShaiArgs,
+        config: FileConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut stdout = io::stdout().lock();
-        crossterm::execute!(stdout, EnterAlternateScreen)?;
-        let backend = CrosstermBackend::new(stdout);
-        let term = Terminal::new(backend)?;
+        // the popup is already sized by tmux, so it uses all of it
+        let height = args.display().height.filter(|_| !viewport::in_tmux_popup());
+        let term = match height {
+            Some(height) => {
+                let (_, rows) = crossterm::terminal::size()?;
+                Terminal::with_options(
+                    CrosstermBackend::new(stdout),
+                    TerminalOptions {
+                        viewport: Viewport::Inline(height.lines(rows)),
+                    },
+                )?
+            }
+            None => {
+                crossterm::execute!(stdout, EnterAlternateScreen)?;
+                Terminal::new(CrosstermBackend::new(stdout))?
+            }
+        };
 
-        let cli_text = args
-            .edit_file()
-            .as_ref()
-            .and_then(|file| fs::read_to_string(file).ok())
-            .map(|bufstr| bufstr.trim().to_string())
-            .unwrap_or_default();
+        let (cli_text, insertion, failure) = initial_prompt(&args)?;
 
         Ok(ShaiUI {
+            redactor: Redactor::new(&config.redaction),
             args,
+            config,
+            session_usage: Usage::default(),
+            session_cost: None,
             term,
             layout: Layout::InputResponse,
             input_text: cli_text.clone(),
@@ -498,12 +1605,19 @@ impl<'t> ShaiUI<'t> {
             auxiliary_response: Response::default(),
             main_response_size: 3,
             response_focus: Focus::MainResponse,
+            insertion,
+            failure,
+            inline: height.is_some(),
+            disabled_sections: HashSet::new(),
+            inspector: None,
+            prepared: None,
         })
     }
 
-    fn new(args: ShaiArgs) -> Result<Self, Box<dyn std::error::Error>> {
+    fn new(args: ShaiArgs, config: FileConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        check_terminal_input()?;
         enable_raw_mode().expect("Terminal needs to be set in raw mode for Shai UI to work");
-        match Self::initialization(args) {
+        match Self::initialization(args, config) {
             Ok(shai) => Ok(shai),
             Err(err) => {
                 disable_raw_mode()?;
@@ -517,29 +1631,51 @@ impl<'t> ShaiUI<'t> {
 
         // restore terminal mode
         disable_raw_mode()?;
-        crossterm::execute!(self.term.backend_mut(), LeaveAlternateScreen,)?;
+        if self.inline {
+            // leave the cursor where the interface started, like fzf does
+            self.term.clear()?;
+        } else {
+            crossterm::execute!(self.term.backend_mut(), LeaveAlternateScreen,)?;
+        }
         self.term.show_cursor()?;
 
         let write_mode = write_mode?;
-        if let ShaiArgs::Ask(_) = self.args {
+        // an analysis writes the fix generated from it, when it is accepted
+        let response = match (&self.args, &write_mode) {
+            (ShaiArgs::Analyze(_), WriteBuffer::Yes | WriteBuffer::Raw) => {
+                self.redactor.restore(&self.auxiliary_response.text)
+            }
+            _ => self.redactor.restore(&self.main_response.text),
+        };
+        if let ShaiArgs::Ask(_) | ShaiArgs::Fix(_) | ShaiArgs::Analyze(_) | ShaiArgs::Translate(_) =
+            self.args
+        {
             if let Some(file) = &self.args.edit_file() {
-                match write_mode {
+                let response = match write_mode {
                     WriteBuffer::Yes => {
-                        let code_blocks = extract_code_blocks(&self.main_response.text);
+                        let code_blocks = extract_code_blocks(&response);
                         if code_blocks.is_empty() {
                             // the model probably obeyed the instructions
-                            fs::write(file, &self.main_response.text)?;
+                            Some(response.clone())
                         } else {
-                            fs::write(file, code_blocks.join("\n"))?;
+                            Some(code_blocks.join("\n"))
                         }
                     }
-                    WriteBuffer::Raw => fs::write(file, &self.main_response.text)?,
-                    WriteBuffer::No => (),
+                    WriteBuffer::Raw => Some(response.clone()),
+                    WriteBuffer::No => None,
+                };
+                match (response, &self.insertion) {
+                    (Some(fragment), Some(insertion)) => {
+                        let (line, sidecar) = insertion.splice(&fragment)?;
+                        fs::write(file, line)?;
+                        sidecar.write(file)?;
+                    }
+                    (Some(response), None) => fs::write(file, response)?,
+                    (None, _) => (),
                 }
             }
         }
         if self.args.write_stdout() {
-            let response = &self.main_response.text;
             println!("{response}");
         }
         Ok(())
@@ -551,7 +1687,7 @@ impl<'t> ShaiUI<'t> {
             self.auxiliary_response.request_state,
         ) {
             (ShaiRequestProgress::None, ShaiRequestProgress::None) => match self.args {
-                ShaiArgs::Ask(_) => {
+                ShaiArgs::Ask(_) | ShaiArgs::Fix(_) | ShaiArgs::Translate(_) => {
                     if self.main_response.text.is_empty() {
                         ShaiState::Started
                     } else if self.auxiliary_response.text.is_empty() {
@@ -567,17 +1703,53 @@ impl<'t> ShaiUI<'t> {
                         ShaiState::ExplanationGenerated
                     }
                 }
+                ShaiArgs::Analyze(_) => {
+                    if self.main_response.text.is_empty() {
+                        ShaiState::Started
+                    } else if self.auxiliary_response.text.is_empty() {
+                        ShaiState::AnalysisGenerated
+                    } else {
+                        ShaiState::AnalysisFixGenerated
+                    }
+                }
             },
             _ => ShaiState::Processing,
         }
     }
 
+    /// The request whose response ended in an error, if any.
+    const fn failed_request(&self) -> Option<RequestType> {
+        if self.auxiliary_response.error.is_some() {
+            Some(RequestType::Auxiliary)
+        } else if self.main_response.error.is_some() {
+            Some(RequestType::Normal)
+        } else {
+            None
+        }
+    }
+
     #[allow(clippy::too_many_lines)]
     async fn mainloop(&mut self) -> Result<WriteBuffer, Box<dyn std::error::Error>> {
+        // the failed command or the output is enough to start with, the prompt can refine it
+        // afterwards
+        let translating =
+            matches!(self.args, ShaiArgs::Translate(_)) && !self.input_text.is_empty();
+        if (self.failure.is_some() || translating || matches!(self.args, ShaiArgs::Analyze(_)))
+            && matches!(
+                self.send_request(RequestType::Normal, false).await?,
+                RequestExit::Exit
+            )
+        {
+            return Ok(WriteBuffer::No);
+        }
         loop {
             self.draw()?;
 
             if let Event::Key(key) = crossterm::event::read()? {
+                if self.inspector.is_some() && self.inspector_key(key) {
+                    continue;
+                }
+                let failed_request = self.failed_request();
                 match key {
                     KeyEvent {
                         code: KeyCode::Char('c'),
@@ -590,7 +1762,9 @@ impl<'t> ShaiUI<'t> {
                         ..
                     } if matches!(
                         self.state(),
-                        ShaiState::CommandGenerated | ShaiState::AuxExplanationGenerated
+                        ShaiState::CommandGenerated
+                            | ShaiState::AuxExplanationGenerated
+                            | ShaiState::AnalysisFixGenerated
                     ) =>
                     {
                         return Ok(WriteBuffer::Raw)
@@ -601,7 +1775,9 @@ impl<'t> ShaiUI<'t> {
                         ..
                     } if matches!(
                         self.state(),
-                        ShaiState::CommandGenerated | ShaiState::AuxExplanationGenerated
+                        ShaiState::CommandGenerated
+                            | ShaiState::AuxExplanationGenerated
+                            | ShaiState::AnalysisFixGenerated
                     ) =>
                     {
                         return Ok(WriteBuffer::Yes)
@@ -611,12 +1787,32 @@ impl<'t> ShaiUI<'t> {
                         ..
                     } => {
                         if matches!(
-                            self.send_request(RequestType::Normal).await?,
+                            self.send_request(RequestType::Normal, false).await?,
                             RequestExit::Exit
                         ) {
                             return Ok(WriteBuffer::No);
                         }
                     }
+                    KeyEvent {
+                        code: KeyCode::Char('k'),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    } => self.inspector = Some(self.inspect()),
+                    // resume (or retry if nothing was received) a request that failed
+                    KeyEvent {
+                        code: KeyCode::Char('o'),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    } => {
+                        if let Some(request_type) = failed_request {
+                            if matches!(
+                                self.send_request(request_type, true).await?,
+                                RequestExit::Exit
+                            ) {
+                                return Ok(WriteBuffer::No);
+                            }
+                        }
+                    }
                     KeyEvent {
                         code: KeyCode::Char('e'),
                         modifiers: KeyModifiers::CONTROL,
@@ -629,7 +1825,44 @@ impl<'t> ShaiUI<'t> {
                         self.layout = Layout::InputResponseExplanation;
                         self.response_focus = Focus::AuxiliaryResponse;
                         if matches!(
-                            self.send_request(RequestType::Auxiliary).await?,
+                            self.send_request(RequestType::Auxiliary, false).await?,
+                            RequestExit::Exit
+                        ) {
+                            return Ok(WriteBuffer::No);
+                        }
+                    }
+                    // translate the buffer line to the shell shai runs for
+                    KeyEvent {
+                        code: KeyCode::Char('t'),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    } if self.translatable() => {
+                        if let Some(translate) = self.translation() {
+                            self.args = ShaiArgs::Translate(translate);
+                            // the same prompt is now a translation
+                            self.prepared = None;
+                            if matches!(
+                                self.send_request(RequestType::Normal, false).await?,
+                                RequestExit::Exit
+                            ) {
+                                return Ok(WriteBuffer::No);
+                            }
+                        }
+                    }
+                    // generate a fix from the analysis of the output
+                    KeyEvent {
+                        code: KeyCode::Char('f'),
+                        modifiers: KeyModifiers::CONTROL,
+                        ..
+                    } if matches!(
+                        self.state(),
+                        ShaiState::AnalysisGenerated | ShaiState::AnalysisFixGenerated
+                    ) =>
+                    {
+                        self.layout = Layout::InputResponseExplanation;
+                        self.response_focus = Focus::AuxiliaryResponse;
+                        if matches!(
+                            self.send_request(RequestType::Auxiliary, false).await?,
                             RequestExit::Exit
                         ) {
                             return Ok(WriteBuffer::No);
@@ -642,7 +1875,10 @@ impl<'t> ShaiUI<'t> {
                         ..
                     } if matches!(
                         self.state(),
-                        ShaiState::ExplanationGenerated | ShaiState::AuxExplanationGenerated
+                        ShaiState::ExplanationGenerated
+                            | ShaiState::AuxExplanationGenerated
+                            | ShaiState::AnalysisGenerated
+                            | ShaiState::AnalysisFixGenerated
                     ) =>
                     {
                         match self.response_focus {
@@ -676,7 +1912,11 @@ impl<'t> ShaiUI<'t> {
                         code: dirchar @ (KeyCode::Up | KeyCode::Down),
                         modifiers: KeyModifiers::SHIFT,
                         ..
-                    } if matches!(self.state(), ShaiState::AuxExplanationGenerated) => {
+                    } if matches!(
+                        self.state(),
+                        ShaiState::AuxExplanationGenerated | ShaiState::AnalysisFixGenerated
+                    ) =>
+                    {
                         if dirchar == KeyCode::Up {
                             self.main_response_size =
                                 self.main_response_size.saturating_sub(1).max(3);
@@ -703,15 +1943,163 @@ impl<'t> ShaiUI<'t> {
         }
     }
 
+    /// Whether the command in the input can be translated with `Ctrl+t`.
+    fn translatable(&self) -> bool {
+        matches!(self.args, ShaiArgs::Ask(_))
+            && self.insertion.is_none()
+            && !self.input.value().trim().is_empty()
+    }
+
+    /// The arguments to translate the command in the input to the shell of the session, `None`
+    /// if that shell is not one shai knows.
+    fn translation(&self) -> Option<TranslateArgs> {
+        let (ShaiArgs::Ask(ask), ConfigKind::Ask(config)) =
+            (&self.args, ConfigKind::from(self.args.clone()))
+        else {
+            return None;
+        };
+        Some(TranslateArgs {
+            from: None,
+            to: Shell::from_model_name(&config.shell)?,
+            ask: ask.clone(),
+        })
+    }
+
+    /// The task, the prompt and the context of the request for `prompt`, prepared only if the
+    /// last request prepared was for another prompt.
+    fn prepare_request(
+        &mut self,
+        request_type: RequestType,
+        prompt: String,
+    ) -> (Task, String, Context) {
+        let prepared = match self.prepared.take() {
+            Some(prepared)
+                if prepared.request_type == request_type && prepared.prompt == prompt =>
+            {
+                prepared
+            }
+            _ => {
+                let (task, user_prompt, context) = prepare_request(
+                    &self.args,
+                    request_type,
+                    prompt.clone(),
+                    self.insertion.as_ref(),
+                    self.failure.as_ref(),
+                    &mut self.redactor,
+                );
+                PreparedRequest {
+                    request_type,
+                    prompt,
+                    task,
+                    user_prompt,
+                    context,
+                }
+            }
+        };
+        let request = (
+            prepared.task,
+            prepared.user_prompt.clone(),
+            prepared.context.clone(),
+        );
+        self.prepared = Some(prepared);
+        request
+    }
+
+    /// Prepare the next request to show it in the inspector.
+    fn inspect(&mut self) -> Inspector {
+        let (task, user_prompt, context) =
+            self.prepare_request(RequestType::Normal, self.input.value().to_string());
+        let model = self.args.model();
+        let context = fit_context(
+            &self.config.context,
+            &model,
+            task,
+            &user_prompt,
+            context.without(&self.disabled_sections),
+        );
+        Inspector {
+            task,
+            request_tokens: estimate_tokens(&task_request(&user_prompt)),
+            sections: context.sections(),
+            selected: 0,
+            trim_note: context.trim_note(),
+        }
+    }
+
+    /// Handle a key while the inspector is open, `false` if it is left for the main loop.
+    fn inspector_key(&mut self, key: KeyEvent) -> bool {
+        let Some(inspector) = &mut self.inspector else {
+            return false;
+        };
+        match (key.code, key.modifiers) {
+            (KeyCode::Up, _) => inspector.selected = inspector.selected.saturating_sub(1),
+            (KeyCode::Down, _) => {
+                inspector.selected =
+                    (inspector.selected + 1).min(inspector.sections.len().saturating_sub(1));
+            }
+            (KeyCode::Char(' '), _) => {
+                let selected = inspector.selected;
+                if let Some(section) = inspector.sections.get(selected) {
+                    if !self.disabled_sections.remove(section.name) {
+                        self.disabled_sections.insert(section.name);
+                    }
+                }
+                // what fits the budget depends on the sections left out
+                let inspector = self.inspect();
+                self.inspector = Some(Inspector {
+                    selected: selected.min(inspector.sections.len().saturating_sub(1)),
+                    ..inspector
+                });
+            }
+            (KeyCode::Esc, _) | (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                self.inspector = None;
+            }
+            (KeyCode::Enter, _) => {
+                self.inspector = None;
+                return false;
+            }
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => return false,
+            _ => (),
+        }
+        true
+    }
+
     fn draw(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let state = self.state();
+        let resumable = self.failed_request().is_some();
+        let translatable = self.translatable();
+        let usage_summary = self.usage_summary();
+        let title = self.title();
         self.term.draw(|f| {
+            if let Some(inspector) = &self.inspector {
+                let chunks = ratatui::layout::Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(3),
+                        Constraint::Min(3),
+                        Constraint::Length(2),
+                    ])
+                    .split(f.size());
+                f.render_widget(
+                    create_input_paragraph(self.input_text.clone(), title.clone()),
+                    chunks[0],
+                );
+                f.render_widget(
+                    create_inspector_paragraph(inspector, &self.disabled_sections),
+                    chunks[1],
+                );
+                f.render_widget(
+                    create_inspector_controls_paragraph(usage_summary.clone()),
+                    chunks[2],
+                );
+                return;
+            }
             let layout = self.layout.create(self.main_response_size);
             let chunks = layout.split(f.size());
             let width = chunks[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
             let scroll = self.input.visual_scroll(width as usize);
             f.render_widget(
-                create_input_paragraph(self.input_text.clone(), Self::title(&self.args))
+                create_input_paragraph(self.input_text.clone(), title.clone())
                     .scroll((0, u16::try_from(scroll).unwrap_or_default())),
                 chunks[0],
             );
@@ -738,8 +2126,7 @@ impl<'t> ShaiUI<'t> {
                 };
             f.render_widget(
                 create_explanation_paragraph(
-                    self.main_response.text.clone(),
-                    self.main_response.request_state,
+                    &self.main_response,
                     matches!(self.response_focus, Focus::MainResponse),
                 )
                 .scroll((main_scroll, 0)),
@@ -747,7 +2134,15 @@ impl<'t> ShaiUI<'t> {
             );
             match &self.layout {
                 Layout::InputResponse => {
-                    f.render_widget(create_controls_paragraph(state), chunks[2]);
+                    f.render_widget(
+                        create_controls_paragraph(
+                            state,
+                            resumable,
+                            translatable,
+                            usage_summary.clone(),
+                        ),
+                        chunks[2],
+                    );
                 }
                 Layout::InputResponseExplanation => {
                     let chunk = chunks[2];
@@ -769,14 +2164,21 @@ impl<'t> ShaiUI<'t> {
                     };
                     f.render_widget(
                         create_explanation_paragraph(
-                            self.auxiliary_response.text.clone(),
-                            self.auxiliary_response.request_state,
+                            &self.auxiliary_response,
                             matches!(self.response_focus, Focus::AuxiliaryResponse),
                         )
                         .scroll((aux_scroll, 0)),
                         chunk,
                     );
-                    f.render_widget(create_controls_paragraph(state), chunks[3]);
+                    f.render_widget(
+                        create_controls_paragraph(
+                            state,
+                            resumable,
+                            translatable,
+                            usage_summary.clone(),
+                        ),
+                        chunks[3],
+                    );
                 }
             }
         })?;
@@ -809,32 +2211,66 @@ impl<'t> ShaiUI<'t> {
 
     // Source = {stdin, main_response}
     // Destination = {main_response, auxiliary_response}
+    // When resuming, the text received before the failure is kept and sent along so the model
+    // continues it.
     async fn send_request(
         &mut self,
         request_type: RequestType,
+        resume: bool,
     ) -> Result<RequestExit, Box<dyn std::error::Error>> {
-        let config = ConfigKind::from(self.args.clone());
-        let model = config.model().clone();
-        let task = match config {
-            ConfigKind::Ask(_) => match request_type {
-                RequestType::Normal => Task::GenerateCommand,
-                RequestType::Auxiliary => Task::Explain,
-            },
-            ConfigKind::Explain(_) => Task::Explain,
-        };
-        let context = Context::from(config);
-        let user_prompt = match request_type {
+        let model = self.args.model();
+        let prompt = match request_type {
             RequestType::Normal => self.input.value().to_string(),
             RequestType::Auxiliary => self.main_response.text.clone(),
         };
-        let request_task = tokio::spawn(model_stream_request(
-            model.clone(),
-            user_prompt,
-            context.clone(),
+        let (task, user_prompt, context) = self.prepare_request(request_type, prompt);
+        let context = fit_context(
+            &self.config.context,
+            &model,
             task,
-        ));
+            &user_prompt,
+            context.without(&self.disabled_sections),
+        );
+        let trim_note = context.trim_note();
+        let partial_response = match request_type {
+            RequestType::Normal => &self.main_response.text,
+            RequestType::Auxiliary => &self.auxiliary_response.text,
+        }
+        .to_string();
+        let partial_response = (resume && !partial_response.trim().is_empty())
+            .then(|| self.redactor.redact(&partial_response));
+
+        // a resumed response depends on the partial text, so it is not worth caching
+        let cache = if self.args.no_cache() || partial_response.is_some() {
+            None
+        } else {
+            ResponseCache::open_default(&self.config.cache)
+        };
+        let cache_key = ResponseCache::key(
+            &model.api_name(),
+            task.system_prompt(),
+            &String::from(context.clone()),
+            &user_prompt,
+        );
+        let cached_response = cache.as_ref().and_then(|cache| cache.get(&cache_key));
+        let from_cache = cached_response.is_some();
+
+        let request_task = match cached_response {
+            Some(response) => tokio::spawn(async move { Ok(cache::replay(response).boxed()) }),
+            None => tokio::spawn({
+                let request = model_stream_request(
+                    model.clone(),
+                    user_prompt,
+                    context.clone(),
+                    task,
+                    partial_response.clone(),
+                );
+                async move { request.await.map(StreamExt::boxed) }
+            }),
+        };
         let mut reqstate = RequestState::WaitRequest;
 
+        let mut request_usage = None;
         let ret = loop {
             self.draw()?;
             match reqstate {
@@ -856,32 +2292,106 @@ impl<'t> ShaiUI<'t> {
                     }
                     if request_task.is_finished() {
                         reqstate = RequestState::Streaming;
-                        self.clear_response(request_type);
+                        match &partial_response {
+                            Some(_) => self.resume_response(request_type),
+                            None => self.clear_response(request_type),
+                        }
+                        self.response_mut(request_type).cached = from_cache;
+                        self.response_mut(request_type).trim_note = trim_note.clone();
                     }
                 }
-                RequestState::Streaming => {
-                    break self
-                        .stream_response(
-                            request_task.await??,
-                            request_type,
-                        )
-                        .await
-                }
+                RequestState::Streaming => match request_task.await? {
+                    Ok(response_stream) => {
+                        break self
+                            .stream_response(response_stream, request_type, &mut request_usage)
+                            .await
+                    }
+                    Err(err) => {
+                        self.set_response_error(err, request_type);
+                        break Ok(RequestExit::Finished);
+                    }
+                },
             }
             self.update_request_state(request_type, false);
         };
         self.update_request_state(request_type, true);
+        if let Some(request_usage) = request_usage {
+            self.record_usage(&model, &task, request_usage);
+        }
+        if let (Some(cache), Ok(RequestExit::Finished)) = (&cache, &ret) {
+            let response = self.response_mut(request_type);
+            if !from_cache && response.error.is_none() && !response.text.is_empty() {
+                // a failing cache should not get in the way of the user
+                cache
+                    .put(&cache_key, &model.api_name(), &response.text)
+                    .ok();
+            }
+        }
         ret
     }
 
+    fn response_mut(&mut self, request_type: RequestType) -> &mut Response {
+        match request_type {
+            RequestType::Normal => &mut self.main_response,
+            RequestType::Auxiliary => &mut self.auxiliary_response,
+        }
+    }
+
+    fn record_usage(&mut self, model: &ModelKind, task: &Task, request_usage: Usage) {
+        let record = LedgerRecord::new(
+            model.provider(),
+            model.api_name(),
+            task.name(),
+            request_usage,
+            &self.config,
+        );
+        self.session_usage.add(request_usage);
+        if let Some(cost) = record.cost {
+            self.session_cost = Some(self.session_cost.unwrap_or_default() + cost);
+        }
+        if let Some(ledger) = Ledger::open_default() {
+            // failing to keep the ledger should not get in the way of the user
+            ledger.append(&record).ok();
+        }
+    }
+
+    fn usage_summary(&self) -> String {
+        if self.session_usage == Usage::default() {
+            return String::new();
+        }
+        let mut summary = format!(
+            " Tokens in: {} out: {} ",
+            self.session_usage.input_tokens, self.session_usage.output_tokens
+        );
+        if let Some(cost) = self.session_cost {
+            summary += &format!("| ${cost:.4} ");
+        }
+        summary
+    }
+
     async fn stream_response(
         &mut self,
-        mut response_stream: impl Stream<Item = Result<String, ModelError>> + Unpin,
+        mut response_stream: impl Stream<Item = Result<StreamChunk, ModelError>> + Unpin,
         request_type: RequestType,
+        request_usage: &mut Option<Usage>,
     ) -> Result<RequestExit, Box<dyn std::error::Error>> {
         while let Some(message) = response_stream.next().await {
             // TODO: dont block on await
-            self.append_message_response(&message?, request_type);
+            match message {
+                Ok(StreamChunk::Text(message)) => {
+                    self.append_message_response(&message, request_type);
+                }
+                Ok(StreamChunk::Usage(usage)) => {
+                    request_usage
+                        .get_or_insert_with(Usage::default)
+                        .merge(usage);
+                }
+                Ok(StreamChunk::Done) => (),
+                Err(err) => {
+                    self.set_response_error(err, request_type);
+                    return Ok(RequestExit::Finished);
+                }
+            }
             self.draw()?;
             if crossterm::event::poll(Duration::from_millis(100))? {
                 if let Event::Key(key) = crossterm::event::read()? {
@@ -917,30 +2427,198 @@ impl<'t> ShaiUI<'t> {
         }
     }
 
-    fn append_message_response(&mut self, response: &str, request_type: RequestType) {
-        let old_text = match request_type {
-            RequestType::Normal => &self.main_response.text,
-            RequestType::Auxiliary => &self.auxiliary_response.text,
-        };
-        let new = format!("{old_text}{response}");
+    /// Keep the text received so far, the continuation is appended to it.
+    fn resume_response(&mut self, request_type: RequestType) {
+        let response = self.response_mut(request_type);
+        response.error = None;
+        response.resuming = true;
+    }
+
+    fn set_response_error(&mut self, error: ModelError, request_type: RequestType) {
         match request_type {
-            RequestType::Normal => self.main_response.text = new,
-            RequestType::Auxiliary => self.auxiliary_response.text = new,
+            RequestType::Normal => self.main_response.error = Some(error),
+            RequestType::Auxiliary => self.auxiliary_response.error = Some(error),
         }
     }
 
-    fn title(args: &ShaiArgs) -> String {
-        match args {
-            ShaiArgs::Ask(_) => "What should shai's command do?",
-            ShaiArgs::Explain(_) => "What command should shai explain?",
+    fn append_message_response(&mut self, response: &str, request_type: RequestType) {
+        let current = self.response_mut(request_type);
+        // the Anthropic prefill is sent without the whitespace the text ends with, so the
+        // continuation may repeat it
+        if current.resuming && !response.is_empty() {
+            current.resuming = false;
+            if response.starts_with(char::is_whitespace) {
+                current.text.truncate(current.text.trim_end().len());
+            }
+        }
+        current.text.push_str(response);
+    }
+
+    fn title(&self) -> String {
+        match (&self.args, &self.insertion) {
+            (ShaiArgs::Ask(_), None) => "What should shai's command do?".to_string(),
+            (ShaiArgs::Ask(_), Some(insertion)) => format!(
+                "What should shai insert at the cursor? {}▏{}",
+                insertion.before, insertion.after
+            ),
+            (ShaiArgs::Explain(_), _) => "What command should shai explain?".to_string(),
+            (ShaiArgs::Fix(args), _) => format!(
+                "Anything else shai should know to fix `{}`?",
+                args.command.trim()
+            ),
+            (ShaiArgs::Analyze(_), _) => "What should shai tell about the output?".to_string(),
+            (ShaiArgs::Translate(args), _) => match args.from {
+                Some(from) => format!(
+                    "What command should shai translate from {} to {}?",
+                    from.model_name(),
+                    args.to.model_name()
+                ),
+                None => format!(
+                    "What command should shai translate to {}?",
+                    args.to.model_name()
+                ),
+            },
         }
-        .to_string()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::extract_code_blocks;
+    use clap::{Parser, ValueEnum};
+
+    use std::collections::HashSet;
+
+    use super::{
+        context_report, extract_code_blocks, prepare_request, resolve_environment,
+        translate_request, RequestType, ShaiArgs, ShaiCLIArgs,
+    };
+    use crate::config::RedactionConfig;
+    use crate::context::detect::Environment;
+    use crate::context::Context;
+    use crate::integration::{self, Shell};
+    use crate::model::Task;
+    use crate::redact::Redactor;
+    use crate::{AskConfig, ConfigKind};
+
+    /// The shai invocations of a generated script, from the subcommand up to `--edit-file` or
+    /// the end of the zsh command array.
+    fn invocations(script: &str) -> Vec<String> {
+        script
+            .match_indices("shai ")
+            .filter_map(|(start, _)| {
+                let line = script[start + "shai ".len()..].lines().next()?;
+                let end = line.find(" --edit-file").or_else(|| line.rfind(')'))?;
+                let invocation = &line[..end];
+                let invocation = invocation
+                    .strip_prefix("-ArgumentList \"")
+                    .unwrap_or(invocation);
+                Some(invocation.to_string())
+            })
+            .filter(|invocation| {
+                ["ask ", "explain ", "fix "]
+                    .iter()
+                    .any(|subcommand| invocation.starts_with(subcommand))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_scripts_match_cli() {
+        let args = ShaiCLIArgs::parse_from([
+            "shai",
+            "generate-script",
+            "--shell",
+            "bash",
+            "--extra-flags",
+            "--cwd --depth 2 --environment 'MY VAR'",
+        ]);
+        let ShaiCLIArgs::GenerateScript(args) = args else {
+            panic!("expected generate-script");
+        };
+        for shell in Shell::value_variants() {
+            let script = args.script.script_options(*shell).unwrap().render();
+            assert!(
+                !script.contains("{{"),
+                "unrendered placeholder for {shell:?}"
+            );
+            let invocations = invocations(&script);
+            assert_eq!(invocations.len(), 4, "invocations for {shell:?}");
+            for invocation in invocations {
+                // undo the escaping of the string the invocation is embedded in
+                let invocation = match shell {
+                    Shell::PowerShell => invocation.replace("\\`\"", "\\\"").replace('`', ""),
+                    _ => invocation,
+                };
+                let mut argv = vec!["shai".to_string()];
+                argv.extend(integration::split_args(&invocation).unwrap());
+                argv.extend(["--edit-file".to_string(), "/tmp/buffer".to_string()]);
+                if invocation.starts_with("fix ") {
+                    argv.extend(["--command".to_string(), "false".to_string()]);
+                }
+                assert!(argv.iter().any(|arg| arg == "MY VAR"), "{argv:?}");
+                if let Err(err) = ShaiCLIArgs::try_parse_from(&argv) {
+                    panic!("{shell:?} script does not match the CLI: {err}\n{argv:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extra_flags_per_subcommand() {
+        let script_options = |extra_flags: &str| {
+            let args = ShaiCLIArgs::parse_from([
+                "shai",
+                "generate-script",
+                "--shell",
+                "zsh",
+                "--extra-flags",
+                extra_flags,
+            ]);
+            let ShaiCLIArgs::GenerateScript(args) = args else {
+                panic!("expected generate-script");
+            };
+            args.script.script_options(args.shell)
+        };
+        // --program is not accepted by explain, it is left out of its invocation
+        let options = script_options("--program ls -p 'fd' --cwd").unwrap();
+        let ask_flags = ["--program", "ls", "-p", "fd", "--cwd"].map(String::from);
+        assert!(options.shai_args("ask").ends_with(&ask_flags));
+        assert!(options.shai_args("fix").ends_with(&ask_flags));
+        let explain = options.shai_args("explain");
+        assert!(explain.ends_with(&["--cwd".to_string()]));
+        assert!(!explain.contains(&"ls".to_string()));
+        // flags no subcommand accepts are still rejected
+        assert!(script_options("--no-such-flag").is_err());
+        assert!(script_options("--depth two").is_err());
+    }
+
+    #[test]
+    fn detected_environment_yields_to_flags() {
+        let detected = Environment {
+            operating_system: Some("Fedora Linux 39".to_string()),
+            shell: Some("zsh".to_string()),
+            tools: vec!["dnf".to_string()],
+        };
+        let (operating_system, shell, tools) =
+            resolve_environment(None, Some("Fish".to_string()), Some(detected));
+        assert_eq!(operating_system, "Fedora Linux 39");
+        assert_eq!(shell, "Fish");
+        assert_eq!(tools, Some(vec!["dnf".to_string()]));
+        assert_eq!(
+            resolve_environment(None, None, None),
+            ("Linux".to_string(), "Bash".to_string(), None)
+        );
+
+        let args =
+            ShaiCLIArgs::parse_from(["shai", "generate-script", "--shell", "fish", "--detect"]);
+        let ShaiCLIArgs::GenerateScript(args) = args else {
+            panic!("expected generate-script");
+        };
+        let options = args.script.script_options(args.shell).unwrap();
+        let shai_args = options.shai_args("ask");
+        assert!(shai_args.contains(&"--detect".to_string()));
+        assert!(!shai_args.contains(&"--operating-system".to_string()));
+    }
 
     #[test]
     fn code_blocks_regex() {
@@ -980,4 +2658,88 @@ Some text after the code block
         assert_eq!(blocks[1], code_no_tag);
         assert_eq!(blocks[2], code_python);
     }
+
+    #[test]
+    fn report_of_what_is_sent() {
+        let context =
+            Context::from(ConfigKind::Ask(AskConfig::default())).without(&HashSet::from(["shell"]));
+        let report = context_report(Task::GenerateCommand, "list files", context);
+        assert!(report.starts_with("System prompt (ask, ~"));
+        assert!(report.contains("  os                   ~12 tokens\n"));
+        assert!(report.contains("  shell                ~"));
+        assert!(report.contains("tokens (disabled)\n"));
+        assert!(report.contains("machine.\nHere is your <task>: \n <task>list files</task>\n"));
+        assert!(!report.contains("You are allowed to use Bash specific features"));
+    }
+
+    #[test]
+    fn analysis_and_fix_from_it() {
+        let ShaiCLIArgs::Analyze(args) =
+            ShaiCLIArgs::parse_from(["shai", "analyze", "--model", "open-aigpt4o-mini"])
+        else {
+            panic!("expected analyze");
+        };
+        let args =
+            ShaiArgs::Analyze(args).with_stdin(Some("error[E0425]: cannot find value `x`".into()));
+        let mut redactor = Redactor::new(&RedactionConfig::default());
+        let (task, prompt, context) = prepare_request(
+            &args,
+            RequestType::Normal,
+            String::new(),
+            None,
+            None,
+            &mut redactor,
+        );
+        assert!(matches!(task, Task::AnalyzeOutput));
+        assert_eq!(prompt, "Explain this output");
+        assert!(String::from(context).contains("```\nerror[E0425]: cannot find value `x`\n```"));
+        let (task, prompt, _) = prepare_request(
+            &args,
+            RequestType::Auxiliary,
+            "`x` is not declared".into(),
+            None,
+            None,
+            &mut redactor,
+        );
+        assert!(matches!(task, Task::GenerateCommand));
+        assert!(prompt.starts_with("Provide the commands that solve the problem"));
+        assert!(prompt.ends_with("analysis of the attached output:\n`x` is not declared"));
+    }
+
+    #[test]
+    fn translation_between_shells() {
+        let ShaiCLIArgs::Translate(args) = ShaiCLIArgs::parse_from([
+            "shai",
+            "translate",
+            "--from",
+            "bash",
+            "--to",
+            "nushell",
+            "--shell",
+            "zsh",
+            "--model",
+            "open-aigpt4o-mini",
+        ]) else {
+            panic!("expected translate");
+        };
+        let mut redactor = Redactor::new(&RedactionConfig::default());
+        let (task, prompt, context) = prepare_request(
+            &ShaiArgs::Translate(args),
+            RequestType::Normal,
+            "ls *.log | wc -l".into(),
+            None,
+            None,
+            &mut redactor,
+        );
+        assert!(matches!(task, Task::Translate));
+        assert_eq!(
+            prompt,
+            "Translate this Bash command to Nushell:\nls *.log | wc -l"
+        );
+        assert!(String::from(context).contains("The shell you are running is Nushell."));
+        assert_eq!(
+            translate_request(None, Shell::Fish, "export A=1"),
+            "Translate this command, written for another shell, to Fish:\nexport A=1"
+        );
+    }
 }
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::usage::Price;
+
+/// Settings read from the user's configuration file.
+///
+/// Everything is optional, a missing file is the same as an empty one.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FileConfig {
+    /// Price overrides keyed by the model API name, e.g. `gpt-4o`.
+    pub(crate) prices: HashMap<String, Price>,
+    pub(crate) cache: CacheConfig,
+    pub(crate) redaction: RedactionConfig,
+    pub(crate) context: ContextConfig,
+}
+
+/// Limits on the context sent along with each request, and the opt-in sources always sent.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ContextConfig {
+    /// Tokens of context sent to any model, it is trimmed beyond this.
+    pub(crate) budget: Option<usize>,
+    /// Budget overrides keyed by the model API name, e.g. `gpt-4.1-nano`.
+    pub(crate) models: HashMap<String, usize>,
+    /// Always send the kubectl context, as with `--kubernetes`.
+    pub(crate) kubernetes: bool,
+    /// Always send the container engine and the running containers, as with `--containers`.
+    pub(crate) containers: bool,
+    /// Always send the configured ssh hosts, as with `--ssh-hosts`.
+    pub(crate) ssh_hosts: bool,
+}
+
+impl ContextConfig {
+    /// The budget for `model`, `None` if only its context window limits the context.
+    pub(crate) fn budget(&self, model: &str) -> Option<usize> {
+        self.models.get(model).copied().or(self.budget)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CacheConfig {
+    pub(crate) enabled: bool,
+    /// How long a cached response is reused, e.g. `7d`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub(crate) ttl: Duration,
+    /// Maximum number of responses kept, the oldest ones are evicted first.
+    pub(crate) max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: Duration::from_secs(7 * 24 * 60 * 60),
+            max_entries: 500,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RedactionConfig {
+    /// Replace the secrets found in requests with placeholders before sending them.
+    pub(crate) enabled: bool,
+    /// Extra patterns of secrets, when a pattern has groups only the first matching group is
+    /// redacted.
+    #[serde(deserialize_with = "deserialize_regexes")]
+    pub(crate) patterns: Vec<Regex>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum ConfigError {
+    #[error("Could not read config file {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("Invalid config file {0}: {1}")]
+    Parse(PathBuf, serde_yaml::Error),
+}
+
+impl FileConfig {
+    /// Location of the config file, `$SHAI_CONFIG` takes precedence over
+    /// `<config dir>/shai/config.yaml`.
+    pub(crate) fn path() -> Option<PathBuf> {
+        std::env::var_os("SHAI_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("shai").join("config.yaml")))
+    }
+
+    pub(crate) fn load() -> Result<Self, ConfigError> {
+        match Self::path() {
+            Some(path) if path.exists() => {
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|err| ConfigError::Read(path.clone(), err))?;
+                Self::parse(&text).map_err(|err| ConfigError::Parse(path, err))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, serde_yaml::Error> {
+        if text.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(text)
+    }
+}
+
+/// Parse a relative duration like `30d`, `12h`, `2w` or `90m`.
+pub(crate) fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let invalid = || format!("Invalid duration '{text}', expected e.g. 30d");
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Unknown duration unit '{unit}', use one of m, h, d, w"
+            ))
+        }
+    };
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_regexes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Directory where shai keeps its own data, like the usage ledger.
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("shai"))
+}
+
+/// Directory where shai keeps data that can be safely deleted, like cached responses.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("shai"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_duration, FileConfig};
+
+    #[test]
+    fn empty_config() {
+        assert!(FileConfig::parse("").unwrap().prices.is_empty());
+    }
+
+    #[test]
+    fn price_override() {
+        let config = FileConfig::parse(
+            "prices:
+  gpt-4o:
+    input: 2.0
+    output: 8.0
+",
+        )
+        .unwrap();
+        let price = &config.prices["gpt-4o"];
+        assert!((price.input - 2.0).abs() < f64::EPSILON);
+        assert!((price.output - 8.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn since_units() {
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration(&format!("{}w", u64::MAX / 2)).is_err());
+    }
+
+    #[test]
+    fn cache_ttl() {
+        let config = FileConfig::parse("cache:\n  ttl: 12h\n").unwrap();
+        assert_eq!(config.cache.ttl, Duration::from_secs(12 * 3600));
+        assert!(config.cache.enabled);
+        assert!(FileConfig::parse("cache:\n  ttl: soon\n").is_err());
+    }
+
+    #[test]
+    fn redaction_patterns() {
+        let config = FileConfig::parse("redaction:\n  patterns: ['corp-[0-9a-f]{32}']\n").unwrap();
+        assert!(config.redaction.enabled);
+        assert!(config.redaction.patterns[0].is_match("corp-0123456789abcdef0123456789abcdef"));
+        assert!(FileConfig::parse("redaction:\n  patterns: ['(']\n").is_err());
+    }
+
+    #[test]
+    fn context_budget_per_model() {
+        let config =
+            FileConfig::parse("context:\n  budget: 4000\n  models:\n    gpt-4.1-nano: 1500\n")
+                .unwrap();
+        assert_eq!(config.context.budget("gpt-4.1-nano"), Some(1500));
+        assert_eq!(config.context.budget("gpt-4o"), Some(4000));
+        assert_eq!(FileConfig::default().context.budget("gpt-4o"), None);
+    }
+
+    #[test]
+    fn context_sources() {
+        let config =
+            FileConfig::parse("context:\n  kubernetes: true\n  ssh_hosts: true\n").unwrap();
+        assert!(config.context.kubernetes);
+        assert!(!config.context.containers);
+        assert!(config.context.ssh_hosts);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(FileConfig::parse("prises: {}").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+
+/// Lines kept from the start of an attachment.
+const HEAD_LINES: usize = 40;
+
+/// Lines kept from the end of an attachment, the ones between the head and the tail are omitted.
+const TAIL_LINES: usize = 40;
+
+/// Characters kept from the start and from the end, so a few very long lines don't fill the
+/// context.
+const HEAD_CHARS: usize = 4000;
+const TAIL_CHARS: usize = 4000;
+
+/// Characters kept of each line, the rest is cut.
+const MAX_LINE_CHARS: usize = 300;
+
+fn cut_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((cut, _)) => format!("{}...", &line[..cut]),
+        None => line.to_string(),
+    }
+}
+
+/// The first and last lines read from `reader`, with a marker counting the ones omitted in
+/// between. `None` if the contents look binary.
+pub(crate) fn sample(mut reader: impl BufRead) -> io::Result<Option<String>> {
+    let mut head: Vec<String> = Vec::new();
+    let mut head_chars = 0;
+    let mut tail: VecDeque<String> = VecDeque::new();
+    let mut tail_chars = 0;
+    let mut total = 0;
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        if buffer.contains(&0) {
+            return Ok(None);
+        }
+        total += 1;
+        let line = cut_line(String::from_utf8_lossy(&buffer).trim_end_matches(['\n', '\r']));
+        let chars = line.chars().count() + 1;
+        if tail.is_empty() && head.len() < HEAD_LINES && head_chars + chars <= HEAD_CHARS {
+            head.push(line);
+            head_chars += chars;
+            continue;
+        }
+        tail.push_back(line);
+        tail_chars += chars;
+        while tail.len() > TAIL_LINES || (tail_chars > TAIL_CHARS && tail.len() > 1) {
+            let dropped = tail.pop_front().unwrap_or_default();
+            tail_chars -= dropped.chars().count() + 1;
+        }
+    }
+    let omitted = total - head.len() - tail.len();
+    let mut lines = head;
+    if omitted > 0 {
+        lines.push(format!("[... {omitted} lines omitted ...]"));
+    }
+    lines.extend(tail);
+    Ok(Some(lines.join("\n")))
+}
+
+fn attach_file(path: &PathBuf) -> String {
+    let sampled = File::open(path).and_then(|file| sample(BufReader::new(file)));
+    match sampled {
+        Ok(Some(text)) => format!("{}:\n```\n{text}\n```\n", path.display()),
+        Ok(None) => format!(
+            "{}: a binary file, its contents are not shown\n",
+            path.display()
+        ),
+        Err(err) => format!("{}: could not be read, {err}\n", path.display()),
+    }
+}
+
+/// The files attached with `--file` and what was piped into shai, already sampled.
+pub(crate) fn attachments(files: &[PathBuf], stdin: Option<&str>) -> Option<String> {
+    let mut attached: String = files.iter().map(attach_file).collect();
+    if let Some(stdin) = stdin {
+        attached += &format!("The input piped to shai:\n```\n{stdin}\n```\n");
+    }
+    (!attached.is_empty()).then_some(attached)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample, MAX_LINE_CHARS};
+
+    #[test]
+    fn head_and_tail() {
+        let log: String = (1..=1000).map(|n| format!("line {n}\n")).collect();
+        let sampled = sample(log.as_bytes()).unwrap().unwrap();
+        let lines: Vec<&str> = sampled.lines().collect();
+        assert_eq!(lines.len(), 81);
+        assert_eq!(lines[0], "line 1");
+        assert_eq!(lines[39], "line 40");
+        assert_eq!(lines[40], "[... 920 lines omitted ...]");
+        assert_eq!(lines[41], "line 961");
+        assert_eq!(lines[80], "line 1000");
+        assert_eq!(sample("a\r\nb".as_bytes()).unwrap().unwrap(), "a\nb");
+    }
+
+    #[test]
+    fn size_caps() {
+        let long = "x".repeat(MAX_LINE_CHARS * 2);
+        let log: String = (0..100).map(|_| format!("{long}\n")).collect();
+        let sampled = sample(log.as_bytes()).unwrap().unwrap();
+        assert!(sampled.chars().count() < 8500, "{}", sampled.len());
+        assert!(sampled.contains(&format!("\n{}...\n", "x".repeat(MAX_LINE_CHARS))));
+        assert!(sampled.contains("lines omitted"));
+        assert_eq!(sample(&b"\x7fELF\x02\x01\x00\x00"[..]).unwrap(), None);
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::integration::Shell;
+
+/// Programs worth telling the model about, they change which command is the best answer. Debian
+/// installs fd and bat as fdfind and batcat.
+pub(crate) const RELEVANT_TOOLS: [&str; 29] = [
+    "fd",
+    "fdfind",
+    "rg",
+    "jq",
+    "yq",
+    "fzf",
+    "bat",
+    "batcat",
+    "eza",
+    "git",
+    "gh",
+    "curl",
+    "wget",
+    "rsync",
+    "docker",
+    "podman",
+    "kubectl",
+    "systemctl",
+    "journalctl",
+    "ip",
+    "apt",
+    "dnf",
+    "yum",
+    "pacman",
+    "zypper",
+    "apk",
+    "nix",
+    "brew",
+    "flatpak",
+];
+
+/// Facts about the machine shai runs on, used instead of the defaults of `--operating-system`
+/// and `--shell`.
+#[derive(Debug, Default)]
+pub(crate) struct Environment {
+    /// The distro and its version, e.g. `Fedora Linux 39 (Workstation Edition)`.
+    pub(crate) operating_system: Option<String>,
+    /// The shell shai was launched from, as passed in `--shell`.
+    pub(crate) shell: Option<String>,
+    /// The programs of [`RELEVANT_TOOLS`] found in `PATH`.
+    pub(crate) tools: Vec<String>,
+}
+
+impl Environment {
+    pub(crate) fn detect() -> Self {
+        Self {
+            operating_system: operating_system(),
+            shell: parent_shell()
+                .or_else(login_shell)
+                .map(|shell| shell.model_name().to_string()),
+            tools: installed_tools(&std::env::var_os("PATH").unwrap_or_default()),
+        }
+    }
+}
+
+fn operating_system() -> Option<String> {
+    if cfg!(windows) {
+        return Some("Windows".to_string());
+    }
+    if cfg!(target_os = "macos") {
+        let output = Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Some(format!("macOS {version}").trim().to_string());
+    }
+    ["/etc/os-release", "/usr/lib/os-release"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .and_then(|os_release| distro(&os_release))
+}
+
+/// The distro described by the contents of an os-release file.
+fn distro(os_release: &str) -> Option<String> {
+    let fields: HashMap<&str, &str> = os_release
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches(['"', '\''])))
+        .collect();
+    fields
+        .get("PRETTY_NAME")
+        .map(ToString::to_string)
+        .or_else(|| {
+            let name = fields.get("NAME")?;
+            Some(match fields.get("VERSION_ID") {
+                Some(version) => format!("{name} {version}"),
+                None => (*name).to_string(),
+            })
+        })
+        .filter(|distro| !distro.is_empty())
+}
+
+/// The shell running shai, e.g. the one whose key binding launched it.
+fn parent_shell() -> Option<Shell> {
+    #[cfg(unix)]
+    {
+        let parent = std::os::unix::process::parent_id();
+        let name = std::fs::read_to_string(format!("/proc/{parent}/comm"))
+            .ok()
+            .or_else(|| {
+                let output = Command::new("ps")
+                    .args(["-o", "comm=", "-p", &parent.to_string()])
+                    .output()
+                    .ok()?;
+                Some(String::from_utf8_lossy(&output.stdout).to_string())
+            })?;
+        shell_of_program(&name)
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+fn login_shell() -> Option<Shell> {
+    shell_of_program(&std::env::var("SHELL").ok()?)
+}
+
+/// The shell run by `program`, a path or a process name like `-zsh` for a login shell.
+fn shell_of_program(program: &str) -> Option<Shell> {
+    let name = Path::new(program.trim()).file_name()?.to_str()?;
+    let name = name.trim_start_matches('-');
+    Shell::from_model_name(name.strip_suffix(".exe").unwrap_or(name))
+}
+
+fn installed_tools(path: &std::ffi::OsStr) -> Vec<String> {
+    let dirs: Vec<PathBuf> = std::env::split_paths(path).collect();
+    RELEVANT_TOOLS
+        .iter()
+        .filter(|tool| {
+            dirs.iter()
+                .any(|dir| is_executable(&dir.join(executable_name(tool))))
+        })
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Where `program` is installed, the first match in `PATH`.
+pub(crate) fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(executable_name(program)))
+        .find(|path| is_executable(path))
+}
+
+/// Run `command` capturing its output, killing it if it does not finish within `timeout`. `None`
+/// if it could not be run or did not finish in time.
+pub(crate) fn output_with_timeout(command: &mut Command, timeout: Duration) -> Option<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => return child.wait_with_output().ok(),
+            Ok(None) if start.elapsed() < timeout => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            _ => {
+                child.kill().ok();
+                child.wait().ok();
+                return None;
+            }
+        }
+    }
+}
+
+fn executable_name(tool: &str) -> String {
+    if cfg!(windows) {
+        format!("{tool}.exe")
+    } else {
+        tool.to_string()
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distro, installed_tools, output_with_timeout, shell_of_program};
+    use crate::integration::Shell;
+
+    #[test]
+    fn distro_from_os_release() {
+        let fedora = "NAME=\"Fedora Linux\"\nVERSION_ID=39\nPRETTY_NAME=\"Fedora Linux 39 (Workstation Edition)\"\n";
+        assert_eq!(
+            distro(fedora).as_deref(),
+            Some("Fedora Linux 39 (Workstation Edition)")
+        );
+        assert_eq!(
+            distro("NAME=Alpine\nVERSION_ID=3.19.1\n").as_deref(),
+            Some("Alpine 3.19.1")
+        );
+        assert_eq!(distro("ID=unknown\n"), None);
+    }
+
+    #[test]
+    fn shell_from_process_name() {
+        assert_eq!(shell_of_program("-zsh\n"), Some(Shell::Zsh));
+        assert_eq!(shell_of_program("/usr/bin/fish"), Some(Shell::Fish));
+        assert_eq!(shell_of_program("nu"), Some(Shell::Nushell));
+        assert_eq!(shell_of_program("tmux: server"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tools_in_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (tool, mode) in [("rg", 0o755), ("jq", 0o644), ("dnf", 0o755), ("vim", 0o755)] {
+            let path = dir.path().join(tool);
+            std::fs::write(&path, "").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+        assert_eq!(installed_tools(dir.path().as_os_str()), ["rg", "dnf"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_time_out() {
+        use std::process::Command;
+        use std::time::{Duration, Instant};
+
+        let output = output_with_timeout(Command::new("echo").arg("up"), Duration::from_secs(5));
+        assert_eq!(output.unwrap().stdout, b"up\n");
+        let start = Instant::now();
+        let output = output_with_timeout(Command::new("sleep").arg("5"), Duration::from_millis(50));
+        assert!(output.is_none());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::redact::Redactor;
+
+/// Words of a variable name that mark its value as secret, e.g. `GITHUB_TOKEN`.
+const SECRET_WORDS: [&str; 12] = [
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "PASS",
+    "KEY",
+    "APIKEY",
+    "CREDENTIAL",
+    "CREDENTIALS",
+    "COOKIE",
+    "SESSION",
+    "PRIVATE",
+];
+
+/// Characters of a value sent to the model, the rest is cut.
+const MAX_VALUE_CHARS: usize = 200;
+
+/// Sent instead of the value of a variable that looks secret.
+const MASK: &str = "<masked>";
+
+/// Whether `name` matches `pattern`, where `*` stands for any text and `?` for one character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain(std::iter::once(name.len()))
+                .any(|index| glob_match(rest, &name[index..]))
+        }
+        Some(first) => {
+            let mut chars = name.chars();
+            match chars.next() {
+                Some(c) if first == '?' || first == c => {
+                    glob_match(&pattern[first.len_utf8()..], chars.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// The variables of `defined` selected by `patterns`, in the order of the patterns. A name without
+/// wildcards is selected even if it is not defined.
+fn select<'v>(
+    patterns: &[String],
+    defined: &'v [(String, String)],
+) -> Vec<(String, Option<&'v str>)> {
+    let mut selected: Vec<(String, Option<&str>)> = Vec::new();
+    for pattern in patterns {
+        let matches: Vec<(String, Option<&str>)> = if is_glob(pattern) {
+            defined
+                .iter()
+                .filter(|(name, _)| glob_match(pattern, name))
+                .map(|(name, value)| (name.clone(), Some(value.as_str())))
+                .collect()
+        } else {
+            let value = defined
+                .iter()
+                .find(|(name, _)| name == pattern)
+                .map(|(_, value)| value.as_str());
+            vec![(pattern.clone(), value)]
+        };
+        for (name, value) in matches {
+            if !selected.iter().any(|(known, _)| *known == name) {
+                selected.push((name, value));
+            }
+        }
+    }
+    selected
+}
+
+fn defined_variables() -> Vec<(String, String)> {
+    let mut defined: Vec<(String, String)> = std::env::vars().collect();
+    defined.sort();
+    defined
+}
+
+/// The names of the variables selected by `patterns`, comma separated.
+pub(crate) fn variable_names(patterns: &[String]) -> String {
+    select(patterns, &defined_variables())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Whether the value of `name` should not be sent, by its name or by its contents.
+fn looks_secret(name: &str, value: &str, redactor: &Redactor) -> bool {
+    name.to_uppercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| SECRET_WORDS.contains(&word))
+        || redactor.finds_secret(value)
+}
+
+fn render_values(selected: &[(String, Option<&str>)], redactor: &Redactor) -> Option<String> {
+    let lines: Vec<String> = selected
+        .iter()
+        .filter_map(|(name, value)| Some((name, (*value)?)))
+        .map(|(name, value)| {
+            if looks_secret(name, value, redactor) {
+                return format!("{name}={MASK}");
+            }
+            match value.char_indices().nth(MAX_VALUE_CHARS) {
+                Some((cut, _)) => format!("{name}={}...", &value[..cut]),
+                None => format!("{name}={value}"),
+            }
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// `NAME=value` of the defined variables selected by `patterns`, one per line. The values that
+/// look secret to `redactor` are masked.
+pub(crate) fn variable_values(patterns: &[String], redactor: &Redactor) -> Option<String> {
+    render_values(&select(patterns, &defined_variables()), redactor)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{glob_match, render_values, select};
+    use crate::config::RedactionConfig;
+    use crate::redact::Redactor;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("AWS_*", "AWS_PROFILE"));
+        assert!(glob_match("*_HOME", "JAVA_HOME"));
+        assert!(glob_match("K?BECONFIG", "KUBECONFIG"));
+        assert!(!glob_match("AWS_*", "MY_AWS_PROFILE"));
+        assert!(!glob_match("KUBECONFIG", "KUBECONFIG_OLD"));
+    }
+
+    #[test]
+    fn selected_values_are_masked() {
+        let defined: Vec<(String, String)> = [
+            ("AWS_PROFILE", "staging"),
+            (
+                "AWS_SECRET_ACCESS_KEY",
+                "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+            ),
+            ("DATABASE_URL", "postgres://app:hunter2@db:5432/app"),
+            ("KUBECONFIG", "/home/me/.kube/staging"),
+            ("CORP_ID", "corp-1234"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let patterns =
+            ["KUBECONFIG", "AWS_*", "DATABASE_URL", "CORP_ID", "UNSET"].map(String::from);
+        let selected = select(&patterns, &defined);
+        assert_eq!(
+            selected
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "KUBECONFIG",
+                "AWS_PROFILE",
+                "AWS_SECRET_ACCESS_KEY",
+                "DATABASE_URL",
+                "CORP_ID",
+                "UNSET"
+            ]
+        );
+        // the configured patterns are masked too, even with redaction disabled
+        let redactor = Redactor::new(&RedactionConfig {
+            enabled: false,
+            patterns: vec![Regex::new(r"corp-\d+").unwrap()],
+        });
+        assert_eq!(
+            render_values(&selected, &redactor).unwrap(),
+            "KUBECONFIG=/home/me/.kube/staging
+AWS_PROFILE=staging
+AWS_SECRET_ACCESS_KEY=<masked>
+DATABASE_URL=<masked>
+CORP_ID=<masked>"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::install::xdg_config_dir;
+use crate::integration::Shell;
+
+/// Characters of history sent to the model, older entries are dropped first.
+const HISTORY_BUDGET: usize = 2000;
+
+/// Longer entries are cut, a single pasted script should not take the whole budget.
+const MAX_ENTRY_CHARS: usize = 300;
+
+/// Where a shell keeps its history and how it is stored.
+#[derive(Debug, PartialEq, Eq)]
+enum HistoryFile {
+    /// One command per line, with optional `#<timestamp>` lines.
+    Bash(PathBuf),
+    /// `: <start>:<elapsed>;<command>` lines, metafied.
+    Zsh(PathBuf),
+    /// The YAML-like `- cmd: <command>` records.
+    Fish(PathBuf),
+    /// One command per line.
+    Plain(PathBuf),
+    /// The reedline sqlite database.
+    Sqlite(PathBuf),
+}
+
+impl HistoryFile {
+    /// `histfile` is the bash or zsh history file passed by the integration, the shells don't
+    /// export `HISTFILE`.
+    fn of(shell: Shell, histfile: Option<&Path>) -> Option<Self> {
+        let histfile = histfile
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var_os("HISTFILE").map(PathBuf::from));
+        let home = dirs::home_dir();
+        let file = match shell {
+            Shell::Bash => Self::Bash(histfile.or_else(|| Some(home?.join(".bash_history")))?),
+            Shell::Zsh => Self::Zsh(histfile.or_else(|| {
+                let dir = std::env::var_os("ZDOTDIR").map(PathBuf::from).or(home)?;
+                Some(dir.join(".zsh_history"))
+            })?),
+            Shell::Fish => {
+                let data_dir = std::env::var_os("XDG_DATA_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| Some(home?.join(".local").join("share")))?;
+                Self::Fish(data_dir.join("fish").join("fish_history"))
+            }
+            Shell::Nushell => {
+                let config_dir = if cfg!(windows) {
+                    dirs::config_dir()
+                } else {
+                    xdg_config_dir()
+                }?
+                .join("nushell");
+                let sqlite = config_dir.join("history.sqlite3");
+                if sqlite.exists() {
+                    Self::Sqlite(sqlite)
+                } else {
+                    Self::Plain(config_dir.join("history.txt"))
+                }
+            }
+            Shell::PowerShell => {
+                let dir = if cfg!(windows) {
+                    dirs::data_dir()?
+                        .join("Microsoft")
+                        .join("Windows")
+                        .join("PowerShell")
+                } else {
+                    dirs::data_dir()?.join("powershell")
+                };
+                Self::Plain(dir.join("PSReadLine").join("ConsoleHost_history.txt"))
+            }
+        };
+        Some(file)
+    }
+
+    /// The last `count` commands, oldest first.
+    fn read(&self, count: usize) -> Option<Vec<String>> {
+        let mut entries = match self {
+            Self::Bash(path) => parse_bash(&read_lossy(path)?),
+            Self::Zsh(path) => parse_zsh(&unmetafy(&std::fs::read(path).ok()?)),
+            Self::Fish(path) => parse_fish(&read_lossy(path)?),
+            Self::Plain(path) => parse_plain(&read_lossy(path)?),
+            Self::Sqlite(path) => return read_sqlite(path, count).ok(),
+        };
+        entries.drain(..entries.len().saturating_sub(count));
+        Some(entries)
+    }
+}
+
+/// The last `count` commands of the history of `shell`, the value of `--shell`, read from
+/// `histfile` if given. `None` if the shell is unknown or its history cannot be read.
+pub(crate) fn recent_commands(
+    shell: &str,
+    count: usize,
+    histfile: Option<&Path>,
+) -> Option<String> {
+    let entries = HistoryFile::of(Shell::from_model_name(shell)?, histfile)?.read(count)?;
+    let history = render(&entries);
+    (!history.is_empty()).then_some(history)
+}
+
+fn read_lossy(path: &Path) -> Option<String> {
+    std::fs::read(path)
+        .ok()
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn parse_bash(text: &str) -> Vec<String> {
+    lazy_static! {
+        static ref TIMESTAMP: Regex = Regex::new(r"^#\d+$").expect("The regex should be valid");
+    }
+    text.lines()
+        .filter(|line| !TIMESTAMP.is_match(line))
+        .map(str::to_string)
+        .collect()
+}
+
+/// zsh escapes some bytes of its history file, a 0x83 byte means the next one was xored with 32.
+fn unmetafy(bytes: &[u8]) -> String {
+    let mut unmetafied = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == 0x83 {
+            if let Some(&next) = bytes.next() {
+                unmetafied.push(next ^ 32);
+            }
+        } else {
+            unmetafied.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&unmetafied).to_string()
+}
+
+fn parse_zsh(text: &str) -> Vec<String> {
+    lazy_static! {
+        static ref EXTENDED: Regex = Regex::new(r"^: \d+:\d+;").expect("The regex should be valid");
+    }
+    let mut entries: Vec<String> = Vec::new();
+    let mut continued = false;
+    for line in text.lines() {
+        // multi-line commands are stored with a backslash before each newline
+        let (line, continues) = line
+            .strip_suffix('\\')
+            .map_or((line, false), |line| (line, true));
+        match (continued, entries.last_mut()) {
+            (true, Some(entry)) => {
+                entry.push('\n');
+                entry.push_str(line);
+            }
+            _ => entries.push(EXTENDED.replace(line, "").to_string()),
+        }
+        continued = continues;
+    }
+    entries
+}
+
+/// fish writes a newline as `\n` and a backslash as `\\`.
+fn unescape_fish(command: &str) -> String {
+    let mut unescaped = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            _ => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
+fn parse_fish(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("- cmd: "))
+        .map(unescape_fish)
+        .collect()
+}
+
+fn parse_plain(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+fn read_sqlite(path: &Path, count: usize) -> rusqlite::Result<Vec<String>> {
+    let connection =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement =
+        connection.prepare("SELECT command_line FROM history ORDER BY id DESC LIMIT ?1")?;
+    let mut entries = statement
+        .query_map([count], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    entries.reverse();
+    Ok(entries)
+}
+
+/// The entries, one per line and oldest first, within the history budget.
+fn render(entries: &[String]) -> String {
+    let mut lines = Vec::new();
+    let mut size = 0;
+    let mut previous = None;
+    for entry in entries.iter().rev() {
+        let entry = entry.trim();
+        // repeated commands tell the model nothing new
+        if entry.is_empty() || previous == Some(entry) {
+            continue;
+        }
+        previous = Some(entry);
+        let mut line = entry.to_string();
+        if let Some((cut, _)) = line.char_indices().nth(MAX_ENTRY_CHARS) {
+            line.truncate(cut);
+            line.push_str(" [...]");
+        }
+        size += line.len() + 1;
+        if size > HISTORY_BUDGET {
+            break;
+        }
+        lines.push(line);
+    }
+    lines.reverse();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_bash, parse_fish, parse_zsh, read_sqlite, render, unmetafy};
+
+    #[test]
+    fn bash_timestamps_are_skipped() {
+        assert_eq!(
+            parse_bash("#1700000000\nls -la\n#1700000001\ngit status\n"),
+            ["ls -la", "git status"]
+        );
+    }
+
+    #[test]
+    fn zsh_extended_history() {
+        let history = ": 1700000000:0;kubectl get pods\n: 1700000001:3;for f in *; do\\\necho $f\\\ndone\nls\n";
+        assert_eq!(
+            parse_zsh(history),
+            ["kubectl get pods", "for f in *; do\necho $f\ndone", "ls"]
+        );
+        // ś is 0xc5 0x9b, and zsh metafies the 0x9b
+        assert_eq!(unmetafy(b"echo \xc5\x83\xbb"), "echo ś");
+    }
+
+    #[test]
+    fn fish_history_records() {
+        let history = "- cmd: git push\n  when: 1700000000\n- cmd: echo a\\nb\n  when: 1700000001\n  paths:\n    - b\n- cmd: echo a\\\\nb\n";
+        assert_eq!(parse_fish(history), ["git push", "echo a\nb", "echo a\\nb"]);
+    }
+
+    #[test]
+    fn nushell_sqlite_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE history (id INTEGER PRIMARY KEY, command_line TEXT NOT NULL);
+                 INSERT INTO history (command_line) VALUES ('ls'), ('cd src'), ('cargo test');",
+            )
+            .unwrap();
+        drop(connection);
+        assert_eq!(read_sqlite(&path, 2).unwrap(), ["cd src", "cargo test"]);
+    }
+
+    #[test]
+    fn render_keeps_newest_within_budget() {
+        let entries: Vec<String> = (0..500).map(|i| format!("echo {i:04}")).collect();
+        let rendered = render(&entries);
+        assert!(rendered.len() <= super::HISTORY_BUDGET);
+        assert!(rendered.ends_with("echo 0499"));
+        assert!(!rendered.contains("echo 0000"));
+        let repeated = ["ls".to_string(), "ls".to_string(), "pwd".to_string()];
+        assert_eq!(render(&repeated), "ls\npwd");
+    }
+}
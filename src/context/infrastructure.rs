@@ -0,0 +1,279 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use super::detect::{find_in_path, output_with_timeout};
+
+/// Names listed per source, the rest are only counted.
+const NAMES_PER_SOURCE: usize = 30;
+
+/// Levels of `Include` followed in the ssh configuration.
+const MAX_INCLUDE_DEPTH: usize = 4;
+
+/// The container engine hangs when its daemon or remote context is unreachable.
+const ENGINE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn list(names: &[String]) -> String {
+    let mut list = names
+        .iter()
+        .take(NAMES_PER_SOURCE)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > NAMES_PER_SOURCE {
+        list += &format!(" and {} more", names.len() - NAMES_PER_SOURCE);
+    }
+    list
+}
+
+fn run(program: &Path, args: &[&str]) -> Option<String> {
+    let output = output_with_timeout(Command::new(program).args(args), ENGINE_TIMEOUT)?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The kubeconfig kubectl uses: the first file of `KUBECONFIG`, or `~/.kube/config`.
+fn kubeconfig_path() -> Option<PathBuf> {
+    match std::env::var_os("KUBECONFIG") {
+        Some(paths) => std::env::split_paths(&paths).find(|path| path.is_file()),
+        None => Some(dirs::home_dir()?.join(".kube").join("config")),
+    }
+}
+
+/// The current context and namespace of a kubeconfig, and the other contexts it defines.
+fn describe_kubeconfig(kubeconfig: &str) -> Option<String> {
+    let config: serde_yaml::Value = serde_yaml::from_str(kubeconfig).ok()?;
+    let contexts = config.get("contexts")?.as_sequence()?;
+    let current = config.get("current-context")?.as_str()?;
+    let namespace = contexts
+        .iter()
+        .find(|context| context.get("name").and_then(serde_yaml::Value::as_str) == Some(current))
+        .and_then(|context| context.get("context")?.get("namespace")?.as_str())
+        .unwrap_or("default");
+    let others: Vec<String> = contexts
+        .iter()
+        .filter_map(|context| context.get("name")?.as_str())
+        .filter(|name| *name != current)
+        .map(ToString::to_string)
+        .collect();
+    let mut description = format!("context {current}, namespace {namespace}");
+    if !others.is_empty() {
+        description += &format!(". Other contexts: {}", list(&others));
+    }
+    Some(description)
+}
+
+/// The current kubectl context and namespace, read from the kubeconfig without running kubectl.
+pub(crate) fn kubernetes() -> Option<String> {
+    describe_kubeconfig(&std::fs::read_to_string(kubeconfig_path()?).ok()?)
+}
+
+/// `name (image, status)` of each container in the output of `ps --format`.
+fn running_containers(ps: &str) -> Vec<String> {
+    ps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let name = fields.next().filter(|name| !name.is_empty())?;
+            match (fields.next(), fields.next()) {
+                (Some(image), Some(status)) => Some(format!("{name} ({image}, {status})")),
+                _ => Some(name.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// The container engine in use, its context and the running containers.
+pub(crate) fn containers() -> Option<String> {
+    let (engine, path) = ["docker", "podman"]
+        .iter()
+        .find_map(|engine| Some((*engine, find_in_path(engine)?)))?;
+    let ps = run(
+        &path,
+        &["ps", "--format", "{{.Names}}\t{{.Image}}\t{{.Status}}"],
+    )?;
+    let mut description = match engine {
+        "docker" => {
+            let context = run(&path, &["context", "show"]).unwrap_or_else(|| "default".into());
+            format!("Containers run with docker, context {context}.")
+        }
+        _ => "Containers run with podman.".to_string(),
+    };
+    let containers = running_containers(&ps);
+    if containers.is_empty() {
+        description += " No containers are running.";
+    } else {
+        description += &format!(" The running containers are: {}", list(&containers));
+    }
+    Some(description)
+}
+
+/// The hosts of an ssh configuration, with the address they point to. `include` resolves the
+/// `Include` directives to the contents of the included files, `depth` is the level of inclusion.
+fn ssh_config_hosts(
+    config: &str,
+    depth: usize,
+    include: &mut dyn FnMut(&str) -> Vec<String>,
+) -> Vec<String> {
+    let mut hosts: Vec<(String, Option<String>)> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    for line in config.lines().map(str::trim) {
+        // keywords are case insensitive and may be separated from their value by `=`
+        let Some((keyword, value)) = line
+            .split_once(|c: char| c.is_whitespace() || c == '=')
+            .map(|(keyword, value)| (keyword.to_lowercase(), value.trim_matches([' ', '\t', '='])))
+        else {
+            continue;
+        };
+        match keyword.as_str() {
+            "host" => {
+                current.clear();
+                for pattern in value.split_whitespace() {
+                    if pattern.contains(['*', '?', '!']) {
+                        continue;
+                    }
+                    if !hosts.iter().any(|(host, _)| host == pattern) {
+                        hosts.push((pattern.to_string(), None));
+                    }
+                    current.extend(hosts.iter().position(|(host, _)| host == pattern));
+                }
+            }
+            "match" => current.clear(),
+            "hostname" => {
+                for index in &current {
+                    hosts[*index].1.get_or_insert_with(|| value.to_string());
+                }
+            }
+            "include" if depth < MAX_INCLUDE_DEPTH => {
+                for pattern in value.split_whitespace() {
+                    for included in include(pattern) {
+                        for host in ssh_config_hosts(&included, depth + 1, include) {
+                            hosts.push((host, None));
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    hosts
+        .into_iter()
+        .map(|(host, hostname)| match hostname {
+            Some(hostname) if hostname != host => format!("{host} ({hostname})"),
+            _ => host,
+        })
+        .collect()
+}
+
+/// The files an `Include` of the ssh configuration refers to, a `*` is only expanded in the file
+/// name.
+fn included_files(ssh_dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let pattern = match pattern.strip_prefix("~/") {
+        Some(relative) => dirs::home_dir().unwrap_or_default().join(relative),
+        None => ssh_dir.join(pattern),
+    };
+    let (Some(dir), Some(name)) = (
+        pattern.parent(),
+        pattern.file_name().and_then(|n| n.to_str()),
+    ) else {
+        return Vec::new();
+    };
+    let Some((prefix, suffix)) = name.split_once('*') else {
+        return vec![pattern.clone()];
+    };
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with(prefix) && name.ends_with(suffix))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// The hosts configured in `~/.ssh/config`.
+pub(crate) fn ssh_hosts() -> Option<String> {
+    let ssh_dir = dirs::home_dir()?.join(".ssh");
+    let config = std::fs::read_to_string(ssh_dir.join("config")).ok()?;
+    let mut include = |pattern: &str| {
+        included_files(&ssh_dir, pattern)
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .collect()
+    };
+    let hosts = ssh_config_hosts(&config, 0, &mut include);
+    (!hosts.is_empty()).then(|| list(&hosts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_kubeconfig, running_containers, ssh_config_hosts};
+
+    #[test]
+    fn kubeconfig() {
+        let kubeconfig = "apiVersion: v1
+current-context: staging
+contexts:
+- name: production
+  context:
+    cluster: prod
+- name: staging
+  context:
+    cluster: stage
+    namespace: payments
+";
+        assert_eq!(
+            describe_kubeconfig(kubeconfig).unwrap(),
+            "context staging, namespace payments. Other contexts: production"
+        );
+        assert_eq!(describe_kubeconfig("apiVersion: v1\n"), None);
+    }
+
+    #[test]
+    fn containers_from_ps() {
+        let ps = "web\tnginx:1.25\tUp 2 hours\ndb\tpostgres:16\tUp 3 days (healthy)\n";
+        assert_eq!(
+            running_containers(ps),
+            [
+                "web (nginx:1.25, Up 2 hours)",
+                "db (postgres:16, Up 3 days (healthy))"
+            ]
+        );
+    }
+
+    #[test]
+    fn ssh_hosts_and_includes() {
+        let config = "Include conf.d/*
+Host *
+    ServerAliveInterval 60
+Host bastion jump
+    HostName 203.0.113.7
+    User admin
+Host=db-primary
+    Hostname db-primary
+Match host *.internal
+    HostName ignored
+";
+        let mut include = |pattern: &str| {
+            assert_eq!(pattern, "conf.d/*");
+            vec!["Host work-laptop\n  HostName 10.0.0.12\n".to_string()]
+        };
+        assert_eq!(
+            ssh_config_hosts(config, 0, &mut include),
+            [
+                "work-laptop (10.0.0.12)",
+                "bastion (203.0.113.7)",
+                "jump (203.0.113.7)",
+                "db-primary"
+            ]
+        );
+    }
+}
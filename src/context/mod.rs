@@ -0,0 +1,412 @@
+use crate::insertion::Insertion;
+use crate::redact::Redactor;
+use crate::ConfigKind;
+use std::collections::HashSet;
+use std::path::Path;
+
+pub(crate) mod attachment;
+pub(crate) mod detect;
+mod environment;
+mod git;
+mod history;
+mod infrastructure;
+mod project;
+mod tree;
+mod versions;
+
+/// Sections trimmed first when the context does not fit its budget. The ones not listed (OS,
+/// shell, cwd and command line) are short and are never trimmed.
+const TRIM_ORDER: [&str; 13] = [
+    "tree",
+    "history",
+    "project",
+    "ssh hosts",
+    "containers",
+    "git",
+    "environment values",
+    "environment",
+    "kubernetes",
+    "versions",
+    "tools",
+    "programs",
+    "attachments",
+];
+
+/// A section that would be cut below this many tokens is left out instead.
+const MIN_SECTION_TOKENS: usize = 50;
+
+#[derive(Clone)]
+pub struct Context {
+    pwd: Option<String>,
+    tree: Option<String>,
+    operating_system: String,
+    shell: String,
+    tools: Option<String>,
+    environment: Option<String>,
+    /// Variables whose value is sent, names or globs. `None` unless enabled.
+    value_patterns: Option<Vec<String>>,
+    /// `NAME=value` of the allowed variables, secrets masked. Read when redacting, so the
+    /// configured patterns are masked too.
+    environment_values: Option<String>,
+    programs: Option<String>,
+    /// The last commands run in the shell, oldest first.
+    history: Option<String>,
+    /// Summary of the git repository of the current folder.
+    git: Option<String>,
+    /// The targets, scripts and services of the project manifests around the current folder.
+    project: Option<String>,
+    /// The current kubectl context and namespace.
+    kubernetes: Option<String>,
+    /// The container engine, its context and the running containers.
+    containers: Option<String>,
+    /// The hosts of the ssh configuration.
+    ssh_hosts: Option<String>,
+    /// The command line being edited, with the insertion point marked.
+    command_line: Option<String>,
+    /// Programs whose version is looked up, `None` unless enabled.
+    version_lookup: Option<Vec<String>>,
+    /// The installed version of the programs involved in the request.
+    tool_versions: Option<String>,
+    /// The files and the piped input attached by the user, sampled.
+    attachments: Option<String>,
+    /// Sections left out of what is sent, by name.
+    disabled: HashSet<&'static str>,
+    /// Sections cut or left out to fit the budget, by name.
+    trimmed: Vec<&'static str>,
+    /// Tokens the context had to fit in, when it was trimmed.
+    budget: Option<usize>,
+}
+
+impl From<ConfigKind> for Context {
+    fn from(value: ConfigKind) -> Self {
+        match value {
+            ConfigKind::Ask(config) => Self {
+                pwd: config.cwd.and_then(|_| std::env::var("PWD").ok()),
+                tree: config
+                    .depth
+                    .map(|depth| tree::directory_tree(Path::new("."), depth)),
+                operating_system: config.operating_system,
+                shell: config.shell.clone(),
+                tools: config.tools.as_ref().map(|tools| tools.join(",")),
+                environment: config
+                    .environment
+                    .as_deref()
+                    .map(environment::variable_names),
+                value_patterns: config.environment_values,
+                environment_values: None,
+                programs: config.programs.as_ref().map(|programs| programs.join(",")),
+                history: config.history.and_then(|count| {
+                    history::recent_commands(&config.shell, count, config.history_file.as_deref())
+                }),
+                git: config
+                    .git
+                    .and_then(|()| git::repository_summary(Path::new("."))),
+                project: config
+                    .project
+                    .and_then(|()| project::project_summary(Path::new("."))),
+                kubernetes: config
+                    .kubernetes
+                    .and_then(|()| infrastructure::kubernetes()),
+                containers: config
+                    .containers
+                    .and_then(|()| infrastructure::containers()),
+                ssh_hosts: config.ssh_hosts.and_then(|()| infrastructure::ssh_hosts()),
+                command_line: None,
+                version_lookup: config
+                    .versions
+                    .map(|()| config.programs.clone().unwrap_or_default()),
+                tool_versions: None,
+                attachments: attachment::attachments(
+                    config.files.as_deref().unwrap_or_default(),
+                    config.stdin.as_deref(),
+                ),
+                disabled: HashSet::new(),
+                trimmed: Vec::new(),
+                budget: None,
+            },
+            ConfigKind::Explain(config) => Self {
+                pwd: config.cwd.and_then(|_| std::env::var("PWD").ok()),
+                tree: config
+                    .depth
+                    .map(|depth| tree::directory_tree(Path::new("."), depth)),
+                operating_system: config.operating_system,
+                shell: config.shell.clone(),
+                tools: config.tools.as_ref().map(|tools| tools.join(",")),
+                environment: config
+                    .environment
+                    .as_deref()
+                    .map(environment::variable_names),
+                value_patterns: config.environment_values,
+                environment_values: None,
+                programs: None,
+                history: config.history.and_then(|count| {
+                    history::recent_commands(&config.shell, count, config.history_file.as_deref())
+                }),
+                git: config
+                    .git
+                    .and_then(|()| git::repository_summary(Path::new("."))),
+                project: config
+                    .project
+                    .and_then(|()| project::project_summary(Path::new("."))),
+                kubernetes: config
+                    .kubernetes
+                    .and_then(|()| infrastructure::kubernetes()),
+                containers: config
+                    .containers
+                    .and_then(|()| infrastructure::containers()),
+                ssh_hosts: config.ssh_hosts.and_then(|()| infrastructure::ssh_hosts()),
+                command_line: None,
+                version_lookup: config.versions.map(|()| Vec::new()),
+                tool_versions: None,
+                attachments: attachment::attachments(
+                    config.files.as_deref().unwrap_or_default(),
+                    config.stdin.as_deref(),
+                ),
+                disabled: HashSet::new(),
+                trimmed: Vec::new(),
+                budget: None,
+            },
+        }
+    }
+}
+
+impl Context {
+    /// Tell the model about the command line its answer will be inserted into.
+    pub(crate) fn with_insertion(mut self, insertion: &Insertion) -> Self {
+        self.command_line = Some(format!("{}<cursor>{}", insertion.before, insertion.after));
+        self
+    }
+
+    /// Tell the model the installed version of the programs `request` and `command` refer to, if
+    /// enabled. `command` is the command line the request is about, if any.
+    pub(crate) fn with_tool_versions(mut self, request: &str, command: Option<&str>) -> Self {
+        if let Some(listed) = &self.version_lookup {
+            self.tool_versions = versions::describe(request, command, listed);
+        }
+        self
+    }
+
+    /// Replace the secrets in every section with placeholders.
+    pub(crate) fn redacted(mut self, redactor: &mut Redactor) -> Self {
+        if let Some(patterns) = &self.value_patterns {
+            self.environment_values = environment::variable_values(patterns, redactor);
+        }
+        self.operating_system = redactor.redact(&self.operating_system);
+        self.shell = redactor.redact(&self.shell);
+        for section in [
+            &mut self.pwd,
+            &mut self.tree,
+            &mut self.tools,
+            &mut self.environment,
+            &mut self.environment_values,
+            &mut self.programs,
+            &mut self.history,
+            &mut self.git,
+            &mut self.project,
+            &mut self.kubernetes,
+            &mut self.containers,
+            &mut self.ssh_hosts,
+            &mut self.command_line,
+            &mut self.tool_versions,
+            &mut self.attachments,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *section = redactor.redact(section);
+        }
+        self
+    }
+
+    /// The environment variables exactly as they are sent to the model.
+    pub(crate) fn environment_preview(&self) -> String {
+        self.sections()
+            .into_iter()
+            .filter(|section| section.name.starts_with("environment"))
+            .map(|section| section.text)
+            .collect()
+    }
+
+    /// Cut the sections in [`TRIM_ORDER`] until the enabled ones fit in `budget` tokens.
+    pub(crate) fn fit(mut self, budget: usize) -> Self {
+        let size: usize = self
+            .sections()
+            .iter()
+            .filter(|section| self.is_enabled(section.name))
+            .map(|section| estimate_tokens(&section.text))
+            .sum();
+        let mut excess = size.saturating_sub(budget);
+        for name in TRIM_ORDER {
+            if excess == 0 {
+                break;
+            }
+            if !self.is_enabled(name) {
+                continue;
+            }
+            let Some(field) = self.section_mut(name) else {
+                continue;
+            };
+            let Some(text) = field.take() else {
+                continue;
+            };
+            let tokens = estimate_tokens(&text);
+            if tokens >= excess + MIN_SECTION_TOKENS {
+                *field = Some(cut(&text, (tokens - excess) * 4));
+                excess = 0;
+            } else {
+                excess = excess.saturating_sub(tokens);
+            }
+            self.trimmed.push(name);
+        }
+        if !self.trimmed.is_empty() {
+            self.budget = Some(budget);
+        }
+        self
+    }
+
+    /// What was trimmed to fit the budget, to let the user know.
+    pub(crate) fn trim_note(&self) -> Option<String> {
+        Some(format!(
+            "context trimmed to ~{} tokens: {}",
+            self.budget?,
+            self.trimmed.join(", ")
+        ))
+    }
+
+    fn section_mut(&mut self, name: &str) -> Option<&mut Option<String>> {
+        Some(match name {
+            "tree" => &mut self.tree,
+            "history" => &mut self.history,
+            "project" => &mut self.project,
+            "ssh hosts" => &mut self.ssh_hosts,
+            "containers" => &mut self.containers,
+            "git" => &mut self.git,
+            "environment values" => &mut self.environment_values,
+            "environment" => &mut self.environment,
+            "kubernetes" => &mut self.kubernetes,
+            "versions" => &mut self.tool_versions,
+            "tools" => &mut self.tools,
+            "programs" => &mut self.programs,
+            "attachments" => &mut self.attachments,
+            _ => return None,
+        })
+    }
+
+    /// Leave the sections named in `disabled` out of what is sent.
+    pub(crate) fn without(mut self, disabled: &HashSet<&'static str>) -> Self {
+        self.disabled = disabled.clone();
+        self
+    }
+
+    pub(crate) fn is_enabled(&self, section: &str) -> bool {
+        !self.disabled.contains(section)
+    }
+
+    /// Every section with content, in the order they are sent, including the disabled ones.
+    #[rustfmt::skip]
+    pub(crate) fn sections(&self) -> Vec<Section> {
+        [
+            ("os", Some(format!("The system you are running is a {} machine.\n", self.operating_system))),
+            ("shell", Some(format!("The shell you are running is {}. You are allowed to use {} specific features. ", self.shell, self.shell))),
+            ("cwd", self.pwd.as_ref().map(|cwd| format!("You are currently in folder: {cwd}\n"))),
+            ("tree", self.tree.as_ref().map(|tree| format!("The contents of the current folder are:\n{tree}"))),
+            ("tools", self.tools.as_ref().map(|tools| format!("The following relevant programs are installed, prefer them when they fit the <task>: {tools}\n"))),
+            ("environment", self.environment.as_ref().map(|env| format!("The following environment variables are defined: {env}\n"))),
+            ("environment values", self.environment_values.as_ref().map(|values| format!("The environment variables have these values:\n{values}\n"))),
+            ("programs", self.programs.as_ref().map(|bins| format!("You have the following programs installed in the system, you should only use these programs to accomplish the <task>: {bins}\n"))),
+            ("git", self.git.as_ref().map(|git| format!("The current folder is in a git repository:\n{git}"))),
+            ("project", self.project.as_ref().map(|project| format!("The project in the current folder defines these targets, scripts and services, use their real names:\n{project}"))),
+            ("kubernetes", self.kubernetes.as_ref().map(|kubernetes| format!("kubectl is using {kubernetes}\n"))),
+            ("containers", self.containers.as_ref().map(|containers| format!("{containers}\n"))),
+            ("ssh hosts", self.ssh_hosts.as_ref().map(|hosts| format!("The hosts configured for ssh are: {hosts}\n"))),
+            ("history", self.history.as_ref().map(|history| format!("The last commands run in the shell, oldest first, were:\n{history}\n"))),
+            ("versions", self.tool_versions.as_ref().map(|versions| format!("These are the installed versions of the programs involved, only use flags they support:\n{versions}\n"))),
+            ("attachments", self.attachments.as_ref().map(|attachments| format!("The user attached the following, long ones are cut to their first and last lines:\n{attachments}"))),
+            ("command line", self.command_line.as_ref().map(|line| format!("The command line being edited is: {line}\n"))),
+        ]
+        .into_iter()
+        .filter_map(|(name, text)| Some(Section { name, text: text? }))
+        .collect()
+    }
+}
+
+/// A part of the context, e.g. the directory tree, as it is sent to the model.
+pub(crate) struct Section {
+    pub(crate) name: &'static str,
+    pub(crate) text: String,
+}
+
+/// The start of `text` up to about `max_chars`, cut at a line end when there is one.
+fn cut(text: &str, max_chars: usize) -> String {
+    let note = "\n[cut to fit the context budget]\n";
+    let max_chars = max_chars.saturating_sub(note.len());
+    let end = text
+        .char_indices()
+        .nth(max_chars)
+        .map_or(text.len(), |(end, _)| end);
+    let end = text[..end]
+        .rfind('\n')
+        .filter(|&line| line > 0)
+        .unwrap_or(end);
+    text[..end].to_string() + note
+}
+
+/// Rough number of tokens `text` takes, about 4 characters per token for English and code.
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+impl From<Context> for String {
+    fn from(value: Context) -> Self {
+        value
+            .sections()
+            .into_iter()
+            .filter(|section| value.is_enabled(section.name))
+            .map(|section| section.text)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_tokens, Context};
+    use crate::{AskConfig, ConfigKind};
+
+    fn context() -> Context {
+        let mut context = Context::from(ConfigKind::Ask(AskConfig::default()));
+        context.tree = Some("├── file.txt (10)\n".repeat(100));
+        context.history = Some("git status\n".repeat(20));
+        context.git = Some("Branch: main, with no upstream\nStatus: clean\n".to_string());
+        context
+    }
+
+    #[test]
+    fn fits_by_trimming_in_order() {
+        let context = context().fit(300);
+        let size: usize = context
+            .sections()
+            .iter()
+            .map(|section| estimate_tokens(&section.text))
+            .sum();
+        assert!(size <= 300, "{size} tokens");
+        assert!(context
+            .tree
+            .as_deref()
+            .unwrap()
+            .ends_with("(10)\n[cut to fit the context budget]\n"));
+        assert_eq!(context.history, Some("git status\n".repeat(20)));
+        assert_eq!(
+            context.trim_note().unwrap(),
+            "context trimmed to ~300 tokens: tree"
+        );
+    }
+
+    #[test]
+    fn small_sections_are_dropped() {
+        assert!(context().fit(10_000).trim_note().is_none());
+        let context = context().fit(60);
+        assert_eq!(context.tree, None);
+        assert_eq!(context.history, None);
+        assert_eq!(context.trimmed, ["tree", "history", "git"]);
+    }
+}
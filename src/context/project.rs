@@ -0,0 +1,353 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Names listed per kind of target, the rest are only counted.
+const NAMES_PER_LIST: usize = 20;
+
+/// Parent folders searched for manifests, besides the current one.
+const MAX_PARENTS: usize = 8;
+
+/// Characters of project summary sent to the model.
+const PROJECT_BUDGET: usize = 2000;
+
+/// Describes the manifest in the given folder from its contents.
+type Summarize = fn(&Path, &str) -> Option<String>;
+
+/// Manifest file names and how to summarize them. The first existing name of each entry is used.
+const MANIFESTS: [(&[&str], Summarize); 7] = [
+    (&["Cargo.toml"], cargo),
+    (&["package.json"], npm),
+    (&["GNUmakefile", "makefile", "Makefile"], make),
+    (&["justfile", "Justfile", ".justfile"], just),
+    (&["pyproject.toml"], python),
+    (
+        &[
+            "compose.yaml",
+            "compose.yml",
+            "docker-compose.yaml",
+            "docker-compose.yml",
+        ],
+        compose,
+    ),
+    (&["flake.nix"], flake),
+];
+
+/// `names` joined, keeping the list short.
+fn list<S: AsRef<str>>(names: &[S]) -> String {
+    let mut list = names
+        .iter()
+        .take(NAMES_PER_LIST)
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > NAMES_PER_LIST {
+        list += &format!(" and {} more", names.len() - NAMES_PER_LIST);
+    }
+    list
+}
+
+fn table_keys(table: Option<&toml::Value>) -> Vec<String> {
+    table
+        .and_then(toml::Value::as_table)
+        .map(|table| table.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn cargo(_: &Path, text: &str) -> Option<String> {
+    let manifest: toml::Table = text.parse().ok()?;
+    let mut parts = Vec::new();
+    if let Some(name) = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+    {
+        parts.push(format!("package {name}"));
+    }
+    let members: Vec<&str> = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(toml::Value::as_array)
+        .map(|members| members.iter().filter_map(toml::Value::as_str).collect())
+        .unwrap_or_default();
+    if !members.is_empty() {
+        parts.push(format!("workspace members: {}", list(&members)));
+    }
+    let binaries: Vec<&str> = manifest
+        .get("bin")
+        .and_then(toml::Value::as_array)
+        .map(|bins| {
+            bins.iter()
+                .filter_map(|bin| bin.get("name")?.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !binaries.is_empty() {
+        parts.push(format!("binaries: {}", list(&binaries)));
+    }
+    let features = table_keys(manifest.get("features"));
+    if !features.is_empty() {
+        parts.push(format!("features: {}", list(&features)));
+    }
+    Some(parts.join("; "))
+}
+
+fn npm(dir: &Path, text: &str) -> Option<String> {
+    let manifest: serde_json::Value = serde_json::from_str(text).ok()?;
+    let runner = [
+        ("pnpm-lock.yaml", "pnpm"),
+        ("yarn.lock", "yarn"),
+        ("bun.lockb", "bun"),
+        ("bun.lock", "bun"),
+    ]
+    .iter()
+    .find(|(lockfile, _)| dir.join(lockfile).exists())
+    .map_or("npm", |(_, runner)| runner);
+    let mut parts = vec![format!("run with {runner}")];
+    if let Some(name) = manifest.get("name").and_then(serde_json::Value::as_str) {
+        parts.insert(0, format!("package {name}"));
+    }
+    let scripts: Vec<&String> = manifest
+        .get("scripts")
+        .and_then(serde_json::Value::as_object)
+        .map(|scripts| scripts.keys().collect())
+        .unwrap_or_default();
+    if !scripts.is_empty() {
+        parts.push(format!("scripts: {}", list(&scripts)));
+    }
+    let workspaces: Vec<&str> = manifest
+        .get("workspaces")
+        .and_then(serde_json::Value::as_array)
+        .map(|workspaces| {
+            workspaces
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .collect()
+        })
+        .unwrap_or_default();
+    if !workspaces.is_empty() {
+        parts.push(format!("workspaces: {}", list(&workspaces)));
+    }
+    Some(parts.join("; "))
+}
+
+fn make(_: &Path, text: &str) -> Option<String> {
+    lazy_static! {
+        static ref TARGET: Regex = Regex::new(r"(?m)^([A-Za-z0-9_][\w./-]*)\s*::?(?:[^=]|$)")
+            .expect("The regex should be valid");
+    }
+    let mut targets: Vec<&str> = Vec::new();
+    for target in TARGET
+        .captures_iter(text)
+        .filter_map(|capture| capture.get(1))
+    {
+        if !targets.contains(&target.as_str()) {
+            targets.push(target.as_str());
+        }
+    }
+    Some(format!("targets: {}", list(&targets)))
+}
+
+fn just(_: &Path, text: &str) -> Option<String> {
+    lazy_static! {
+        static ref RECIPE: Regex = Regex::new(r"(?m)^@?([A-Za-z_][\w-]*)[^:\n]*:(?:[^=]|$)")
+            .expect("The regex should be valid");
+    }
+    let recipes: Vec<&str> = RECIPE
+        .captures_iter(text)
+        .filter_map(|capture| Some(capture.get(1)?.as_str()))
+        .filter(|recipe| !["set", "alias", "export", "import", "mod"].contains(recipe))
+        .collect();
+    Some(format!("recipes: {}", list(&recipes)))
+}
+
+fn python(_: &Path, text: &str) -> Option<String> {
+    let manifest: toml::Table = text.parse().ok()?;
+    let mut parts = Vec::new();
+    let project = manifest.get("project");
+    let poetry = manifest.get("tool").and_then(|tool| tool.get("poetry"));
+    if let Some(name) = project
+        .or(poetry)
+        .and_then(|project| project.get("name"))
+        .and_then(toml::Value::as_str)
+    {
+        parts.push(format!("package {name}"));
+    }
+    if let Some(backend) = manifest
+        .get("build-system")
+        .and_then(|build| build.get("build-backend"))
+        .and_then(toml::Value::as_str)
+    {
+        parts.push(format!("build backend {backend}"));
+    }
+    let mut scripts = table_keys(project.and_then(|project| project.get("scripts")));
+    scripts.extend(table_keys(poetry.and_then(|poetry| poetry.get("scripts"))));
+    if !scripts.is_empty() {
+        parts.push(format!("scripts: {}", list(&scripts)));
+    }
+    // the configured tools tell how tests, linting and environments are run
+    let tools = table_keys(manifest.get("tool"));
+    if !tools.is_empty() {
+        parts.push(format!("configured tools: {}", list(&tools)));
+    }
+    Some(parts.join("; "))
+}
+
+fn compose(_: &Path, text: &str) -> Option<String> {
+    let compose: serde_yaml::Value = serde_yaml::from_str(text).ok()?;
+    let services: Vec<&str> = compose
+        .get("services")
+        .and_then(serde_yaml::Value::as_mapping)
+        .map(|services| {
+            services
+                .keys()
+                .filter_map(serde_yaml::Value::as_str)
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(format!("services: {}", list(&services)))
+}
+
+fn flake(_: &Path, text: &str) -> Option<String> {
+    lazy_static! {
+        static ref OUTPUT: Regex = Regex::new(
+            r"\b(packages|devShells?|apps|checks|formatter|overlays?|nixosModules|nixosConfigurations|homeConfigurations|darwinConfigurations|templates|lib)\b\s*(?:\.|=)"
+        )
+        .expect("The regex should be valid");
+    }
+    let mut outputs: Vec<&str> = Vec::new();
+    for output in OUTPUT
+        .captures_iter(text)
+        .filter_map(|capture| capture.get(1))
+    {
+        if !outputs.contains(&output.as_str()) {
+            outputs.push(output.as_str());
+        }
+    }
+    Some(format!("outputs: {}", list(&outputs)))
+}
+
+/// `dir` and its parents, up to the root of the git repository it is in.
+fn searched_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for ancestor in dir.ancestors().take(MAX_PARENTS + 1) {
+        dirs.push(ancestor.to_path_buf());
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+    dirs
+}
+
+/// The manifests in `dir` and its parents and what they offer, one per line.
+pub(crate) fn project_summary(dir: &Path) -> Option<String> {
+    let dir = std::path::absolute(dir).ok()?;
+    let mut summary = String::new();
+    for (level, searched) in searched_dirs(&dir).iter().enumerate() {
+        for (names, summarize) in MANIFESTS {
+            let Some((name, text)) = names
+                .iter()
+                .find_map(|name| Some((*name, std::fs::read_to_string(searched.join(name)).ok()?)))
+            else {
+                continue;
+            };
+            let Some(description) = summarize(searched, &text) else {
+                continue;
+            };
+            let line = format!("- {}{name}: {description}\n", "../".repeat(level));
+            if summary.len() + line.len() > PROJECT_BUDGET {
+                summary += "[more manifests were left out to fit the context]\n";
+                return Some(summary);
+            }
+            summary += &line;
+        }
+    }
+    (!summary.is_empty()).then_some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{cargo, compose, flake, just, make, npm, project_summary, python};
+
+    #[test]
+    fn cargo_workspace() {
+        let manifest = r#"
+[package]
+name = "shai"
+[workspace]
+members = ["crates/core", "crates/cli"]
+[[bin]]
+name = "shai"
+[features]
+live-api-tests = []
+"#;
+        assert_eq!(
+            cargo(Path::new("."), manifest).unwrap(),
+            "package shai; workspace members: crates/core, crates/cli; binaries: shai; features: live-api-tests"
+        );
+    }
+
+    #[test]
+    fn npm_scripts_and_runner() {
+        let manifest = r#"{"name": "web", "scripts": {"dev": "vite", "test": "vitest"}}"#;
+        assert_eq!(
+            npm(Path::new("/nonexistent"), manifest).unwrap(),
+            "package web; run with npm; scripts: dev, test"
+        );
+    }
+
+    #[test]
+    fn make_and_just_targets() {
+        let makefile = ".PHONY: build\nCC := gcc\nbuild: main.o\n\t$(CC) -o app main.o\ntest:\n\t./app --test\n%.o: %.c\n";
+        assert_eq!(
+            make(Path::new("."), makefile).unwrap(),
+            "targets: build, test"
+        );
+        let justfile = "set dotenv-load\nalias t := test\nversion := \"1\"\n\n# run the tests\ntest *args:\n    cargo test {{args}}\n@deploy env='staging': test\n    ./deploy {{env}}\n";
+        assert_eq!(
+            just(Path::new("."), justfile).unwrap(),
+            "recipes: test, deploy"
+        );
+    }
+
+    #[test]
+    fn python_compose_and_flake() {
+        let pyproject = "[project]\nname = \"api\"\n[project.scripts]\napi = \"api:main\"\n[tool.pytest.ini_options]\n[tool.ruff]\n";
+        assert_eq!(
+            python(Path::new("."), pyproject).unwrap(),
+            "package api; scripts: api; configured tools: pytest, ruff"
+        );
+        let compose_file = "services:\n  db:\n    image: postgres\n  web:\n    build: .\n";
+        assert_eq!(
+            compose(Path::new("."), compose_file).unwrap(),
+            "services: db, web"
+        );
+        let flake_file = "{ outputs = { self, nixpkgs }: { packages.x86_64-linux.default = 1; devShells.x86_64-linux.default = 2; }; }";
+        assert_eq!(
+            flake(Path::new("."), flake_file).unwrap(),
+            "outputs: packages, devShells"
+        );
+    }
+
+    #[test]
+    fn manifests_in_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let package = root.join("packages").join("web");
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(root.join("Makefile"), "all:\n\ttrue\n").unwrap();
+        std::fs::write(
+            package.join("package.json"),
+            r#"{"scripts": {"build": "tsc"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            project_summary(&package).unwrap(),
+            "- package.json: run with npm; scripts: build\n- ../../Makefile: targets: all\n"
+        );
+    }
+}
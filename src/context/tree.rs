@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+/// Entries listed per directory, the rest are summarized in a single line.
+const ENTRIES_PER_DIRECTORY: usize = 25;
+
+/// Characters of tree sent to the model.
+const TREE_BUDGET: usize = 4000;
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    /// `None` for directories.
+    size: Option<u64>,
+}
+
+/// The contents of `root` up to `depth` levels, drawn like the `tree` command. Files ignored by
+/// git and hidden files are left out.
+pub(crate) fn directory_tree(root: &Path, depth: u32) -> String {
+    let mut children: HashMap<PathBuf, Vec<Entry>> = HashMap::new();
+    let walk = WalkBuilder::new(root)
+        .max_depth(usize::try_from(depth).ok())
+        .require_git(false)
+        .sort_by_file_name(Ord::cmp)
+        .build();
+    for entry in walk.flatten().filter(|entry| entry.depth() > 0) {
+        let Some(parent) = entry.path().parent() else {
+            continue;
+        };
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        children
+            .entry(parent.to_path_buf())
+            .or_default()
+            .push(Entry {
+                // non UTF-8 names are still worth showing
+                name: entry.file_name().to_string_lossy().to_string(),
+                path: entry.path().to_path_buf(),
+                size: (!is_dir).then(|| entry.metadata().map_or(0, |metadata| metadata.len())),
+            });
+    }
+
+    let mut tree = String::from(".\n");
+    let mut counts = (0, 0);
+    let complete = draw(&children, root, "", &mut tree, &mut counts);
+    let (directories, files) = counts;
+    if !complete {
+        tree += "[the tree was cut to fit the context]\n";
+    }
+    let _ = writeln!(tree, "\n{directories} directories, {files} files");
+    tree
+}
+
+/// Draw the children of `dir` under `prefix`, `false` if the budget ran out.
+fn draw(
+    children: &HashMap<PathBuf, Vec<Entry>>,
+    dir: &Path,
+    prefix: &str,
+    tree: &mut String,
+    counts: &mut (usize, usize),
+) -> bool {
+    let Some(entries) = children.get(dir) else {
+        return true;
+    };
+    let shown = entries.len().min(ENTRIES_PER_DIRECTORY);
+    for (index, entry) in entries[..shown].iter().enumerate() {
+        let last = index + 1 == entries.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let line = match entry.size {
+            Some(size) => format!("{prefix}{branch}{} ({})\n", entry.name, human_size(size)),
+            None => format!("{prefix}{branch}{}\n", entry.name),
+        };
+        if tree.len() + line.len() > TREE_BUDGET {
+            return false;
+        }
+        tree.push_str(&line);
+        match entry.size {
+            Some(_) => counts.1 += 1,
+            None => {
+                counts.0 += 1;
+                if !draw(
+                    children,
+                    &entry.path,
+                    &format!("{prefix}{indent}"),
+                    tree,
+                    counts,
+                ) {
+                    return false;
+                }
+            }
+        }
+    }
+    if entries.len() > shown {
+        let _ = writeln!(tree, "{prefix}└── [{} more entries]", entries.len() - shown);
+    }
+    true
+}
+
+/// Size like `tree -h` prints it, e.g. `4.0K`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1}{}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{directory_tree, human_size};
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(512), "512");
+        assert_eq!(human_size(4096), "4.0K");
+        assert_eq!(human_size(5 * 1024 * 1024 + 1), "5.0M");
+    }
+
+    #[test]
+    fn tree_skips_ignored_and_caps_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("many")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("target/debug/shai"), "binary").unwrap();
+        for index in 0..30 {
+            fs::write(root.join("many").join(format!("{index:02}.txt")), "").unwrap();
+        }
+        let tree = directory_tree(root, 2);
+
+        assert!(tree.starts_with(".\n├── Cargo.toml (9)\n├── many\n│   ├── 00.txt (0)\n"));
+        assert!(tree.contains(
+            "│   ├── 24.txt (0)\n│   └── [5 more entries]\n└── src\n    └── main.rs (12)\n"
+        ));
+        assert!(!tree.contains("target"));
+        assert!(tree.ends_with("\n2 directories, 27 files\n"));
+    }
+}
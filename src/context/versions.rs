@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::detect::{find_in_path, output_with_timeout, RELEVANT_TOOLS};
+use crate::config;
+
+/// Programs whose flags differ between GNU, BSD and BusyBox or between versions, they are looked
+/// up wherever they are mentioned.
+const VERSION_SENSITIVE: [&str; 24] = [
+    "sed", "grep", "find", "awk", "xargs", "tar", "date", "stat", "ls", "cp", "readlink", "sort",
+    "du", "df", "base64", "cut", "ps", "ssh", "curl", "git", "docker", "kubectl", "rsync",
+    "openssl",
+];
+
+/// Versions looked up per request, every lookup runs a program.
+const MAX_PROGRAMS: usize = 8;
+
+/// A `--version` that takes longer than this is not worth waiting for.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ToolVersion {
+    /// First line of the output of `--version`.
+    version: Option<String>,
+    implementation: Option<String>,
+}
+
+/// `--version` output of the installed programs, keyed by their path. An entry is reused while
+/// the program file is unchanged.
+#[derive(Default, Serialize, Deserialize)]
+struct VersionCache {
+    entries: HashMap<String, (u64, ToolVersion)>,
+}
+
+impl VersionCache {
+    fn path() -> Option<PathBuf> {
+        config::cache_dir().map(|dir| dir.join("tool-versions.json"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        // a failing cache only means running --version again next time
+        if let (Some(path), Ok(text)) = (Self::path(), serde_json::to_string(self)) {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).ok();
+            }
+            std::fs::write(path, text).ok();
+        }
+    }
+
+    fn version(&mut self, path: &Path) -> ToolVersion {
+        let modified = path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |modified| modified.as_secs());
+        let key = path.to_string_lossy().to_string();
+        match self.entries.get(&key) {
+            Some((cached_modified, version)) if *cached_modified == modified => version.clone(),
+            _ => {
+                let version = run_version(path);
+                self.entries.insert(key, (modified, version.clone()));
+                version
+            }
+        }
+    }
+}
+
+/// The programs worth running `--version` for: `listed`, the known tools in command position in
+/// `command` and the version sensitive ones mentioned anywhere. Nothing else is run, user scripts
+/// or programs like `reboot` may ignore the flag and just do their job.
+fn referenced_programs(text: &str, command: Option<&str>, listed: &[String]) -> Vec<String> {
+    lazy_static! {
+        static ref COMMAND: Regex =
+            Regex::new(r"(?m)(?:^|[|;&(`]|\$\()\s*(?:sudo\s+)?([A-Za-z0-9][\w.+-]*)")
+                .expect("The regex should be valid");
+        static ref WORD: Regex =
+            Regex::new(r"[A-Za-z0-9][\w.+-]*").expect("The regex should be valid");
+    }
+    let command = command.unwrap_or_default();
+    let in_command_position = COMMAND
+        .captures_iter(command)
+        .filter_map(|capture| Some(capture.get(1)?.as_str()))
+        .filter(|program| RELEVANT_TOOLS.contains(program) || VERSION_SENSITIVE.contains(program));
+    let version_sensitive = WORD
+        .find_iter(text)
+        .chain(WORD.find_iter(command))
+        .map(|word| word.as_str())
+        .filter(|word| VERSION_SENSITIVE.contains(word));
+    let mut programs: Vec<String> = Vec::new();
+    for program in listed
+        .iter()
+        .map(String::as_str)
+        .chain(in_command_position)
+        .chain(version_sensitive)
+    {
+        if !programs.iter().any(|known| known == program) {
+            programs.push(program.to_string());
+        }
+    }
+    programs
+}
+
+/// The installed version of the programs `text` and `command` refer to and of `listed`, one per
+/// line.
+pub(crate) fn describe(text: &str, command: Option<&str>, listed: &[String]) -> Option<String> {
+    let mut cache = VersionCache::load();
+    let lines: Vec<String> = referenced_programs(text, command, listed)
+        .into_iter()
+        .filter_map(|program| Some((find_in_path(&program)?, program)))
+        .take(MAX_PROGRAMS)
+        .map(|(path, program)| render(&program, &cache.version(&path)))
+        .collect();
+    cache.save();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+fn render(program: &str, version: &ToolVersion) -> String {
+    let mut line = format!("{program}: ");
+    line += version.version.as_deref().unwrap_or("unknown version");
+    if let Some(implementation) = &version.implementation {
+        line += &format!(" ({implementation} implementation)");
+    }
+    line
+}
+
+/// Run `path --version`, killing it if it does not answer in time.
+fn run_version(path: &Path) -> ToolVersion {
+    let result = output_with_timeout(Command::new(path).arg("--version"), VERSION_TIMEOUT);
+    let success = result.as_ref().map(|result| result.status.success());
+    let output = result
+        .map(|result| {
+            let stdout = String::from_utf8_lossy(&result.stdout).to_string();
+            if stdout.trim().is_empty() {
+                String::from_utf8_lossy(&result.stderr).to_string()
+            } else {
+                stdout
+            }
+        })
+        .unwrap_or_default();
+    let busybox = std::fs::canonicalize(path)
+        .ok()
+        .and_then(|target| Some(target.file_name()?.to_str()? == "busybox"))
+        .unwrap_or(false);
+    parse_version(&output, success, busybox)
+}
+
+/// The version and implementation from the output of `--version`. `success` is `None` when the
+/// program could not be run in time.
+fn parse_version(output: &str, success: Option<bool>, busybox: bool) -> ToolVersion {
+    let first_line = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.chars().take(80).collect::<String>());
+    let lowercase = output.to_lowercase();
+    let implementation = if busybox || lowercase.contains("busybox") {
+        Some("BusyBox")
+    } else if lowercase.contains("uutils") {
+        Some("uutils")
+    } else if output.contains("GNU") {
+        Some("GNU")
+    } else if lowercase.contains("bsd")
+        || (success == Some(false)
+            && (lowercase.contains("illegal option") || lowercase.contains("unrecognized option")))
+    {
+        // the BSD tools have no --version at all
+        Some("BSD")
+    } else {
+        None
+    };
+    ToolVersion {
+        version: first_line.filter(|_| success == Some(true) && implementation != Some("BSD")),
+        implementation: implementation.map(ToString::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_version, referenced_programs, render};
+
+    #[test]
+    fn programs_in_command_position_and_version_sensitive() {
+        let programs = referenced_programs(
+            "why does it fail",
+            Some("journalctl -u nginx | grep -E 'error' && sudo systemctl restart nginx; deploy"),
+            &["jq".to_string()],
+        );
+        // unknown programs are never run, even in command position
+        assert_eq!(programs, ["jq", "journalctl", "grep", "systemctl"]);
+        let programs = referenced_programs("", Some("./build.sh && halt"), &[]);
+        assert!(programs.is_empty());
+        // the words of the prompt are never run, only the version sensitive ones are looked up
+        let programs = referenced_programs("replace foo with bar in place using sed", None, &[]);
+        assert_eq!(programs, ["sed"]);
+        let programs = referenced_programs("reboot\nrm everything; shutdown now", None, &[]);
+        assert!(programs.is_empty());
+    }
+
+    #[test]
+    fn implementations() {
+        let gnu = parse_version("sed (GNU sed) 4.9\nPackaged by Debian\n", Some(true), false);
+        assert_eq!(
+            render("sed", &gnu),
+            "sed: sed (GNU sed) 4.9 (GNU implementation)"
+        );
+        let bsd = parse_version(
+            "sed: illegal option -- -\nusage: sed script [-Ealnru]",
+            Some(false),
+            false,
+        );
+        assert_eq!(
+            render("sed", &bsd),
+            "sed: unknown version (BSD implementation)"
+        );
+        let busybox = parse_version("", Some(false), true);
+        assert_eq!(busybox.implementation.as_deref(), Some("BusyBox"));
+        let hung = parse_version("", None, false);
+        assert_eq!(render("x", &hung), "x: unknown version");
+    }
+}
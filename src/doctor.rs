@@ -0,0 +1,284 @@
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::config::FileConfig;
+use crate::error::Provider;
+use crate::install::{self, Installation};
+use crate::integration::{self, Shell};
+use crate::ModelError;
+
+const PROVIDERS: [Provider; 2] = [Provider::OpenAI, Provider::Anthropic];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pass => write!(f, "ok"),
+            Self::Warn => write!(f, "warn"),
+            Self::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// Outcome of a single diagnostic.
+pub(crate) struct Check {
+    name: String,
+    pub(crate) status: Status,
+    detail: String,
+    /// What the user can do about a warning or failure.
+    fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        status: Status,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run every diagnostic, the network checks run concurrently.
+pub(crate) async fn run_checks() -> Vec<Check> {
+    let mut checks = vec![config_check()];
+    checks.extend(PROVIDERS.map(api_key_check));
+    checks.extend(futures::future::join_all(PROVIDERS.map(reachability_check)).await);
+    checks.push(terminal_check());
+    checks.extend(integration_checks());
+    checks
+}
+
+fn config_check() -> Check {
+    let name = "config file";
+    let Some(path) = FileConfig::path() else {
+        return Check::pass(name, "no config directory, using the defaults");
+    };
+    match FileConfig::load() {
+        Ok(_) if path.exists() => Check::pass(name, format!("{} is valid", path.display())),
+        Ok(_) => Check::pass(
+            name,
+            format!("{} does not exist, using the defaults", path.display()),
+        ),
+        Err(err) => Check::problem(
+            Status::Fail,
+            name,
+            err.to_string(),
+            "Fix the file, or remove it to use the defaults",
+        ),
+    }
+}
+
+fn api_key_check(provider: Provider) -> Check {
+    let var = provider.api_key_var();
+    let name = format!("{provider} API key");
+    match std::env::var(var) {
+        Ok(key) if key.trim().is_empty() => Check::problem(
+            Status::Fail,
+            name,
+            format!("{var} is set but empty"),
+            format!("export {var}=<your {provider} API key>"),
+        ),
+        Ok(_) => Check::pass(name, format!("{var} is set")),
+        Err(_) => Check::problem(
+            Status::Warn,
+            name,
+            format!("{var} is not set, {provider} models will not work"),
+            format!("export {var}=<your {provider} API key>, if you use {provider} models"),
+        ),
+    }
+}
+
+/// Any HTTP response counts, only the connection is checked.
+async fn reachability_check(provider: Provider) -> Check {
+    let name = format!("{provider} endpoint");
+    let url = provider.api_url();
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            return Check::problem(
+                Status::Fail,
+                name,
+                err.to_string(),
+                "Check your TLS and proxy settings",
+            )
+        }
+    };
+    match client.head(url).send().await {
+        Ok(response) => Check::pass(
+            name,
+            format!("{url} reachable (HTTP {})", response.status().as_u16()),
+        ),
+        Err(err) => {
+            let error = ModelError::from_reqwest(provider, &err);
+            Check::problem(
+                Status::Fail,
+                name,
+                format!("{url} unreachable: {}", error.kind),
+                error
+                    .hint()
+                    .unwrap_or_else(|| "Check your internet connection".to_string()),
+            )
+        }
+    }
+}
+
+fn terminal_check() -> Check {
+    let name = "terminal";
+    if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+        return Check::problem(
+            Status::Warn,
+            name,
+            "TERM=dumb, the interface may not render",
+            "Run shai from a terminal emulator with TERM set, e.g. xterm-256color",
+        );
+    }
+    let raw_mode = crossterm::terminal::enable_raw_mode()
+        .and_then(|()| crossterm::terminal::disable_raw_mode());
+    match (raw_mode, crossterm::terminal::size()) {
+        (Ok(()), Ok((columns, rows))) => {
+            let mut detail = format!("raw mode available, {columns}x{rows}");
+            if !std::io::stdout().is_terminal() {
+                detail += ", stdout is redirected";
+            }
+            Check::pass(name, detail)
+        }
+        (Err(err), _) | (_, Err(err)) => Check::problem(
+            Status::Fail,
+            name,
+            format!("raw mode unavailable: {err}"),
+            "Run shai from an interactive terminal, shell widgets must not redirect its terminal",
+        ),
+    }
+}
+
+fn integration_checks() -> Vec<Check> {
+    let Ok(shells) = install::detect_shells() else {
+        return vec![Check::problem(
+            Status::Warn,
+            "shell integration",
+            "could not detect your shell",
+            "shai install --shell <your shell>",
+        )];
+    };
+    shells.into_iter().map(integration_check).collect()
+}
+
+fn integration_check(shell: Shell) -> Check {
+    let name = format!("{shell:?} integration");
+    let shell_arg = shell
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string());
+    let reinstall = format!("shai install --shell {shell_arg}");
+    let installation = match Installation::of(shell) {
+        Ok(installation) => installation,
+        Err(err) => return Check::problem(Status::Warn, name, err.to_string(), reinstall),
+    };
+    if !installation.is_sourced() {
+        return Check::problem(
+            Status::Warn,
+            name,
+            format!("not sourced by {}", installation.rc_file.display()),
+            reinstall,
+        );
+    }
+    let Ok(script) = std::fs::read_to_string(&installation.script) else {
+        return Check::problem(
+            Status::Fail,
+            name,
+            format!("{} is missing", installation.script.display()),
+            reinstall,
+        );
+    };
+    let current = env!("CARGO_PKG_VERSION");
+    match integration::script_version(&script) {
+        Some(version) if version == current => Check::pass(
+            name,
+            format!("sourced by {}", installation.rc_file.display()),
+        ),
+        version => Check::problem(
+            Status::Warn,
+            name,
+            format!(
+                "generated by shai {}, this is shai {current}",
+                version.unwrap_or("unknown")
+            ),
+            reinstall,
+        ),
+    }
+}
+
+/// Render the checks as a table, with the fixes below the checks that need them.
+pub(crate) fn report(checks: &[Check]) -> String {
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for check in checks {
+        out += &format!(
+            "{:<4}  {:<width$}  {}\n",
+            check.status.to_string(),
+            check.name,
+            check.detail
+        );
+        if let Some(fix) = &check.fix {
+            out += &format!("{:<4}  {:<width$}  fix: {fix}\n", "", "");
+        }
+    }
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    out += &format!(
+        "\n{} passed, {} warnings, {} failed\n",
+        count(Status::Pass),
+        count(Status::Warn),
+        count(Status::Fail)
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Check, Status};
+
+    #[test]
+    fn report_shows_fixes() {
+        let checks = [
+            Check::pass("config file", "valid"),
+            Check::problem(Status::Fail, "tree program", "missing", "install tree"),
+        ];
+        let table = report(&checks);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "ok    config file   valid");
+        assert_eq!(lines[1], "FAIL  tree program  missing");
+        assert_eq!(lines[2], "                    fix: install tree");
+        assert_eq!(lines[4], "1 passed, 0 warnings, 1 failed");
+    }
+}
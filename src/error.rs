@@ -0,0 +1,254 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+
+/// The backend a request was sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Provider {
+    OpenAI,
+    Anthropic,
+}
+
+impl Provider {
+    /// Name of the environment variable holding the API key for this provider.
+    pub(crate) const fn api_key_var(self) -> &'static str {
+        match self {
+            Self::OpenAI => "OPENAI_API_KEY",
+            Self::Anthropic => "ANTHROPIC_API_KEY",
+        }
+    }
+
+    /// Endpoint the requests of this provider are sent to.
+    pub(crate) const fn api_url(self) -> &'static str {
+        match self {
+            Self::OpenAI => "https://api.openai.com/v1/chat/completions",
+            Self::Anthropic => "https://api.anthropic.com/v1/messages",
+        }
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenAI => write!(f, "OpenAI"),
+            Self::Anthropic => write!(f, "Anthropic"),
+        }
+    }
+}
+
+/// What went wrong, independently of the provider that reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorKind {
+    Authentication,
+    RateLimit,
+    Quota,
+    ContextLength,
+    Network,
+    Timeout,
+    BadRequest,
+    Server,
+    StreamInterrupted,
+    InvalidResponse,
+    Unknown,
+}
+
+impl ErrorKind {
+    /// Classify an error response from its HTTP status and the error type/code reported in the
+    /// body. The body fields are checked first as they are more specific than the status.
+    pub(crate) fn classify(status: Option<u16>, error_type: Option<&str>, message: &str) -> Self {
+        let message = message.to_lowercase();
+        match error_type {
+            Some("insufficient_quota" | "billing_error") => return Self::Quota,
+            Some("context_length_exceeded" | "request_too_large") => return Self::ContextLength,
+            Some("authentication_error" | "permission_error" | "invalid_api_key") => {
+                return Self::Authentication
+            }
+            Some("rate_limit_error" | "rate_limit_exceeded") => return Self::RateLimit,
+            Some("overloaded_error" | "api_error" | "server_error") => return Self::Server,
+            _ => (),
+        }
+        if message.contains("context length")
+            || message.contains("context_length")
+            || message.contains("prompt is too long")
+        {
+            return Self::ContextLength;
+        }
+        if message.contains("credit balance") || message.contains("exceeded your current quota") {
+            return Self::Quota;
+        }
+        match status {
+            Some(401 | 403) => Self::Authentication,
+            Some(429) => Self::RateLimit,
+            Some(413) => Self::ContextLength,
+            Some(408 | 504) => Self::Timeout,
+            Some(400 | 404 | 422) => Self::BadRequest,
+            Some(500..=599) => Self::Server,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Authentication => "authentication error",
+            Self::RateLimit => "rate limited",
+            Self::Quota => "quota exceeded",
+            Self::ContextLength => "context too long",
+            Self::Network => "network error",
+            Self::Timeout => "request timed out",
+            Self::BadRequest => "bad request",
+            Self::Server => "server error",
+            Self::StreamInterrupted => "stream interrupted",
+            Self::InvalidResponse => "invalid response",
+            Self::Unknown => "unknown error",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// Error returned by any model request, carrying enough information for the UI to tell the user
+/// what happened and how to fix it.
+#[derive(Debug, Clone, Error)]
+pub(crate) struct ModelError {
+    pub(crate) kind: ErrorKind,
+    pub(crate) provider: Provider,
+    pub(crate) status: Option<u16>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) message: String,
+}
+
+impl ModelError {
+    pub(crate) fn new(kind: ErrorKind, provider: Provider, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            provider,
+            status: None,
+            request_id: None,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub(crate) fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Build an error from a failed `reqwest` call, telling timeouts apart from other transport
+    /// failures.
+    pub(crate) fn from_reqwest(provider: Provider, err: &reqwest::Error) -> Self {
+        let kind = if err.is_timeout() {
+            ErrorKind::Timeout
+        } else if err.is_connect() || err.is_request() {
+            ErrorKind::Network
+        } else if err.is_body() || err.is_decode() {
+            ErrorKind::StreamInterrupted
+        } else {
+            ErrorKind::Unknown
+        };
+        let mut error = Self::new(kind, provider, err.to_string());
+        if let Some(status) = err.status() {
+            error = error.with_status(status.as_u16());
+        }
+        error
+    }
+
+    /// A short suggestion on how the user can get past this error.
+    pub(crate) fn hint(&self) -> Option<String> {
+        match self.kind {
+            ErrorKind::Authentication => Some(format!(
+                "Check that {} is set to a valid API key for {}",
+                self.provider.api_key_var(),
+                self.provider
+            )),
+            ErrorKind::RateLimit => {
+                Some("Wait a moment and send the prompt again, or choose another model".to_string())
+            }
+            ErrorKind::Quota => Some(format!(
+                "Your {} account is out of credits, check your plan and billing details",
+                self.provider
+            )),
+            ErrorKind::ContextLength => Some(
+                "Reduce the context (e.g. a lower --depth) or choose a model with larger context"
+                    .to_string(),
+            ),
+            ErrorKind::Network => {
+                Some("Check your internet connection and proxy settings".to_string())
+            }
+            ErrorKind::Timeout => Some("The provider took too long, try again".to_string()),
+            ErrorKind::BadRequest => Some(
+                "The request was rejected, check that your account has access to this model"
+                    .to_string(),
+            ),
+            ErrorKind::Server | ErrorKind::StreamInterrupted => Some(format!(
+                "{} is having trouble, try again in a moment",
+                self.provider
+            )),
+            ErrorKind::InvalidResponse => Some(
+                "shai could not understand the response, try again or report an issue".to_string(),
+            ),
+            ErrorKind::Unknown => None,
+        }
+    }
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.provider, self.kind)?;
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {status})")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " [request id: {request_id}]")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorKind;
+
+    #[test]
+    fn classify_by_status() {
+        assert_eq!(
+            ErrorKind::classify(Some(401), None, ""),
+            ErrorKind::Authentication
+        );
+        assert_eq!(
+            ErrorKind::classify(Some(429), None, ""),
+            ErrorKind::RateLimit
+        );
+        assert_eq!(ErrorKind::classify(Some(503), None, ""), ErrorKind::Server);
+        assert_eq!(
+            ErrorKind::classify(Some(400), None, ""),
+            ErrorKind::BadRequest
+        );
+    }
+
+    #[test]
+    fn classify_body_takes_precedence() {
+        assert_eq!(
+            ErrorKind::classify(Some(429), Some("insufficient_quota"), ""),
+            ErrorKind::Quota
+        );
+        assert_eq!(
+            ErrorKind::classify(Some(400), Some("context_length_exceeded"), ""),
+            ErrorKind::ContextLength
+        );
+        assert_eq!(
+            ErrorKind::classify(Some(400), None, "prompt is too long: 250000 tokens"),
+            ErrorKind::ContextLength
+        );
+        assert_eq!(
+            ErrorKind::classify(Some(529), Some("overloaded_error"), "Overloaded"),
+            ErrorKind::Server
+        );
+    }
+}
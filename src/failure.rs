@@ -0,0 +1,106 @@
+use std::io;
+use std::path::Path;
+
+/// Lines of error output sent to the model, the end of the output is usually the relevant part.
+const STDERR_LINES: usize = 40;
+
+/// A command that failed, as reported by the shell integration.
+#[derive(Debug, Clone)]
+pub(crate) struct FailedCommand {
+    pub(crate) command: String,
+    pub(crate) status: Option<i32>,
+    /// The last lines of the error output, if it was captured.
+    stderr: Option<String>,
+}
+
+impl FailedCommand {
+    /// A missing `stderr_file` is not an error, the output is only captured when the command was
+    /// run through the `shai-capture` wrapper.
+    pub(crate) fn new(
+        command: String,
+        status: Option<i32>,
+        stderr_file: Option<&Path>,
+    ) -> io::Result<Self> {
+        let stderr = match stderr_file.map(std::fs::read) {
+            Some(Ok(bytes)) => Some(tail(&String::from_utf8_lossy(&bytes), STDERR_LINES)),
+            Some(Err(err)) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => None,
+        };
+        Ok(Self {
+            command: command.trim().to_string(),
+            status,
+            stderr: stderr.filter(|stderr| !stderr.is_empty()),
+        })
+    }
+
+    /// The request sent to the model, `note` is whatever the user typed in the prompt.
+    pub(crate) fn request(&self, note: &str) -> String {
+        let mut request = format!("The command `{}` failed", self.command);
+        if let Some(status) = self.status {
+            request += &format!(" with exit status {status}");
+        }
+        request += ".\n";
+        match &self.stderr {
+            Some(stderr) => {
+                request +=
+                    &format!("Its error output ended with:\n<stderr>\n{stderr}\n</stderr>\n");
+            }
+            None => request += "Its error output was not captured.\n",
+        }
+        let note = note.trim();
+        if !note.is_empty() {
+            request += &format!("Additional information from the user: {note}\n");
+        }
+        request
+    }
+}
+
+/// The last `lines` lines of `text`, without trailing whitespace.
+fn tail(text: &str, lines: usize) -> String {
+    let text = text.trim_end();
+    let start = text
+        .rmatch_indices('\n')
+        .nth(lines.saturating_sub(1))
+        .map_or(0, |(index, _)| index + 1);
+    text[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tail, FailedCommand};
+
+    #[test]
+    fn tail_keeps_last_lines() {
+        assert_eq!(tail("a\nb\nc\n\n", 2), "b\nc");
+        assert_eq!(tail("a\nb", 5), "a\nb");
+        assert_eq!(tail("", 5), "");
+    }
+
+    #[test]
+    fn request_mentions_status_and_stderr() {
+        let stderr = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(stderr.path(), "warning: x\nerror: no such file\n").unwrap();
+        let failure =
+            FailedCommand::new("  cat nope\n".to_string(), Some(1), Some(stderr.path())).unwrap();
+        let request = failure.request("it should print the config");
+        assert!(request.starts_with("The command `cat nope` failed with exit status 1.\n"));
+        assert!(request.contains("<stderr>\nwarning: x\nerror: no such file\n</stderr>"));
+        assert!(
+            request.ends_with("Additional information from the user: it should print the config\n")
+        );
+    }
+
+    #[test]
+    fn missing_stderr_file() {
+        let failure = FailedCommand::new(
+            "make".to_string(),
+            None,
+            Some(std::path::Path::new("/nonexistent/shai-stderr")),
+        )
+        .unwrap();
+        assert_eq!(
+            failure.request(""),
+            "The command `make` failed.\nIts error output was not captured.\n"
+        );
+    }
+}
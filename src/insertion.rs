@@ -0,0 +1,113 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// The shell command line split at the cursor, used when shai inserts its answer at the cursor
+/// instead of replacing the whole line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Insertion {
+    pub(crate) before: String,
+    pub(crate) after: String,
+}
+
+impl Insertion {
+    /// Split `line` at `cursor`, counted in characters. A cursor past the end of the line is
+    /// placed at the end.
+    pub(crate) fn at(line: &str, cursor: usize) -> Self {
+        let split = line
+            .char_indices()
+            .nth(cursor)
+            .map_or(line.len(), |(index, _)| index);
+        Self {
+            before: line[..split].to_string(),
+            after: line[split..].to_string(),
+        }
+    }
+
+    /// The command line with `fragment` inserted, and where the cursor goes: right after the
+    /// inserted text. The spaces around `fragment` are kept, they separate it from the line, but a
+    /// fragment of several lines can't be inserted.
+    pub(crate) fn splice(&self, fragment: &str) -> Result<(String, Sidecar), String> {
+        let fragment = fragment.trim_matches(['\n', '\r']);
+        if fragment.contains(['\n', '\r']) {
+            return Err("The answer has several lines, it can't be inserted at the cursor".into());
+        }
+        let line = format!("{}{fragment}{}", self.before, self.after);
+        let inserted_end = self.before.len() + fragment.len();
+        let sidecar = Sidecar {
+            cursor: line[..inserted_end].chars().count(),
+            cursor_byte: inserted_end,
+        };
+        Ok((line, sidecar))
+    }
+}
+
+/// Written next to the edit file after an insertion, so the shell can place the cursor.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct Sidecar {
+    /// Cursor position in characters.
+    pub(crate) cursor: usize,
+    /// Cursor position in UTF-8 bytes, as used by bash's `READLINE_POINT`.
+    pub(crate) cursor_byte: usize,
+}
+
+impl Sidecar {
+    /// The sidecar of `edit_file` is `<edit_file>.json`.
+    pub(crate) fn path(edit_file: &Path) -> PathBuf {
+        let mut path = OsString::from(edit_file);
+        path.push(".json");
+        PathBuf::from(path)
+    }
+
+    pub(crate) fn write(&self, edit_file: &Path) -> io::Result<()> {
+        let text = serde_json::to_string(self).map_err(io::Error::other)?;
+        std::fs::write(Self::path(edit_file), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Insertion, Sidecar};
+
+    #[test]
+    fn split_at_cursor() {
+        let insertion = Insertion::at("kubectl get pods -o json |  > pods.txt", 27);
+        assert_eq!(insertion.before, "kubectl get pods -o json | ");
+        assert_eq!(insertion.after, " > pods.txt");
+        assert_eq!(Insertion::at("ls", 10).before, "ls");
+    }
+
+    #[test]
+    fn splice_places_cursor_after_fragment() {
+        let insertion = Insertion::at("echo ñandú | ", 13);
+        let (line, sidecar) = insertion.splice("wc -c\n").unwrap();
+        assert_eq!(line, "echo ñandú | wc -c");
+        assert_eq!(sidecar.cursor, 18);
+        assert_eq!(sidecar.cursor_byte, 20);
+        assert_eq!(
+            serde_json::to_string(&sidecar).unwrap(),
+            r#"{"cursor":18,"cursor_byte":20}"#
+        );
+    }
+
+    #[test]
+    fn splice_keeps_surrounding_spaces() {
+        let insertion = Insertion::at("ls|", 2);
+        let (line, sidecar) = insertion.splice(" -la \n").unwrap();
+        assert_eq!(line, "ls -la |");
+        assert_eq!(sidecar.cursor, 7);
+        assert!(insertion.splice("-la\necho done").is_err());
+    }
+
+    #[test]
+    fn sidecar_path() {
+        assert_eq!(
+            Sidecar::path(Path::new("/tmp/tmp.abc")),
+            Path::new("/tmp/tmp.abc.json")
+        );
+    }
+}
@@ -0,0 +1,338 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::config;
+use crate::integration::Shell;
+
+const BLOCK_START: &str = "# >>> shai integration >>>";
+const BLOCK_END: &str = "# <<< shai integration <<<";
+
+#[derive(Debug, Error)]
+pub(crate) enum InstallError {
+    #[error("Could not detect your shell, select it with --shell")]
+    NoShellDetected,
+    #[error("Could not determine the location of the configuration of {0:?}")]
+    UnknownLocation(Shell),
+    #[error("Could not access {0}: {1}")]
+    Io(PathBuf, io::Error),
+}
+
+/// Where the integration of a shell is installed.
+pub(crate) struct Installation {
+    pub(crate) shell: Shell,
+    /// The generated integration script.
+    pub(crate) script: PathBuf,
+    /// The file the shell reads on startup, where the script is sourced.
+    pub(crate) rc_file: PathBuf,
+}
+
+impl Installation {
+    pub(crate) fn of(shell: Shell) -> Result<Self, InstallError> {
+        let unknown = || InstallError::UnknownLocation(shell);
+        let script_name = match shell {
+            Shell::Bash => "bash_assistant.sh",
+            Shell::Zsh => "zsh_assistant.zsh",
+            Shell::Fish => "fish_assistant.fish",
+            Shell::Nushell => "nushell_assistant.nu",
+            Shell::PowerShell => "powershell_assistant.ps1",
+        };
+        let script = config::data_dir()
+            .ok_or_else(unknown)?
+            .join("integration")
+            .join(script_name);
+        Ok(Self {
+            shell,
+            script,
+            rc_file: rc_file(shell).ok_or_else(unknown)?,
+        })
+    }
+
+    /// The line that loads the integration script.
+    fn source_line(&self) -> String {
+        let path = self.script.display().to_string();
+        match self.shell {
+            Shell::Bash | Shell::Zsh => format!("source '{}'", path.replace('\'', r"'\''")),
+            Shell::Fish => format!("source '{}'", path.replace('\'', r"\'")),
+            // nushell resolves the path when parsing, so it has to be a literal
+            Shell::Nushell => format!("source `{path}`"),
+            Shell::PowerShell => format!(". '{}'", path.replace('\'', "''")),
+        }
+    }
+
+    /// The marked block added to the rc file.
+    fn block(&self) -> String {
+        format!(
+            "{BLOCK_START}\n# Added by `shai install`, remove it with `shai uninstall`\n{}\n{BLOCK_END}\n",
+            self.source_line()
+        )
+    }
+
+    /// Write the script and source it from the rc file. Running it again only updates the script
+    /// and the block.
+    pub(crate) fn install(&self, script: &str, dry_run: bool) -> Result<String, InstallError> {
+        let rc = read_or_empty(&self.rc_file)?;
+        let mut report = diff(&self.script, &read_or_empty(&self.script)?, script);
+        report += &diff(&self.rc_file, &rc, &insert_block(&rc, &self.block()));
+        if !dry_run {
+            write(&self.script, script)?;
+            write(&self.rc_file, &insert_block(&rc, &self.block()))?;
+        }
+        Ok(report)
+    }
+
+    /// Remove the block from the rc file and delete the script.
+    pub(crate) fn uninstall(&self, dry_run: bool) -> Result<String, InstallError> {
+        let rc = read_or_empty(&self.rc_file)?;
+        let uninstalled = remove_block(&rc);
+        let mut report = diff(&self.script, &read_or_empty(&self.script)?, "");
+        report += &diff(&self.rc_file, &rc, &uninstalled);
+        if !dry_run {
+            if self.script.exists() {
+                fs::remove_file(&self.script)
+                    .map_err(|err| InstallError::Io(self.script.clone(), err))?;
+            }
+            if uninstalled != rc {
+                write(&self.rc_file, &uninstalled)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Whether the rc file has the block sourcing the script.
+    pub(crate) fn is_sourced(&self) -> bool {
+        fs::read_to_string(&self.rc_file).is_ok_and(|rc| find_block(&rc).is_some())
+    }
+
+    /// Whether there is anything to uninstall.
+    pub(crate) fn is_installed(&self) -> bool {
+        self.script.exists() || self.is_sourced()
+    }
+}
+
+/// Fish and nushell follow the XDG layout on every unix, including macOS.
+pub(crate) fn xdg_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+}
+
+fn rc_file(shell: Shell) -> Option<PathBuf> {
+    match shell {
+        Shell::Bash => dirs::home_dir().map(|home| home.join(".bashrc")),
+        Shell::Zsh => std::env::var_os("ZDOTDIR")
+            .map(PathBuf::from)
+            .or_else(dirs::home_dir)
+            .map(|dir| dir.join(".zshrc")),
+        Shell::Fish => xdg_config_dir().map(|dir| dir.join("fish").join("config.fish")),
+        Shell::Nushell if cfg!(windows) => {
+            dirs::config_dir().map(|dir| dir.join("nushell").join("config.nu"))
+        }
+        Shell::Nushell => xdg_config_dir().map(|dir| dir.join("nushell").join("config.nu")),
+        Shell::PowerShell if cfg!(windows) => dirs::document_dir().map(|dir| {
+            dir.join("PowerShell")
+                .join("Microsoft.PowerShell_profile.ps1")
+        }),
+        Shell::PowerShell => xdg_config_dir().map(|dir| {
+            dir.join("powershell")
+                .join("Microsoft.PowerShell_profile.ps1")
+        }),
+    }
+}
+
+/// The shells the user runs: the login shell and any shell with an existing rc file.
+pub(crate) fn detect_shells() -> Result<Vec<Shell>, InstallError> {
+    let mut shells = Vec::new();
+    let login_shell = std::env::var("SHELL").ok().and_then(|path| {
+        let name = Path::new(&path).file_name()?.to_str()?.to_string();
+        match name.as_str() {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "nu" => Some(Shell::Nushell),
+            "pwsh" => Some(Shell::PowerShell),
+            _ => None,
+        }
+    });
+    shells.extend(login_shell);
+    for shell in [
+        Shell::Bash,
+        Shell::Zsh,
+        Shell::Fish,
+        Shell::Nushell,
+        Shell::PowerShell,
+    ] {
+        if !shells.contains(&shell) && rc_file(shell).is_some_and(|rc| rc.exists()) {
+            shells.push(shell);
+        }
+    }
+    if shells.is_empty() {
+        return Err(InstallError::NoShellDetected);
+    }
+    Ok(shells)
+}
+
+fn read_or_empty(path: &Path) -> Result<String, InstallError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(InstallError::Io(path.to_path_buf(), err)),
+    }
+}
+
+fn write(path: &Path, text: &str) -> Result<(), InstallError> {
+    let io_error = |err| InstallError::Io(path.to_path_buf(), err);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    fs::write(path, text).map_err(io_error)
+}
+
+/// Byte range of the marked block, including its trailing newline.
+fn find_block(rc: &str) -> Option<(usize, usize)> {
+    let start = rc.find(BLOCK_START)?;
+    let end = start + rc[start..].find(BLOCK_END)? + BLOCK_END.len();
+    let end = if rc[end..].starts_with('\n') {
+        end + 1
+    } else {
+        end
+    };
+    Some((start, end))
+}
+
+/// Replace the block in `rc`, or append it separated by an empty line.
+fn insert_block(rc: &str, block: &str) -> String {
+    if let Some((start, end)) = find_block(rc) {
+        return format!("{}{block}{}", &rc[..start], &rc[end..]);
+    }
+    match rc {
+        "" => block.to_string(),
+        rc if rc.ends_with('\n') => format!("{rc}\n{block}"),
+        rc => format!("{rc}\n\n{block}"),
+    }
+}
+
+/// Remove the block from `rc`, along with the empty line that separates it from the line before.
+fn remove_block(rc: &str) -> String {
+    let Some((start, end)) = find_block(rc) else {
+        return rc.to_string();
+    };
+    let before = &rc[..start];
+    let before = if before.ends_with("\n\n") {
+        &before[..before.len() - 1]
+    } else {
+        before
+    };
+    format!("{before}{}", &rc[end..])
+}
+
+/// First line of a hunk, empty hunks refer to the line before them.
+const fn hunk_start(prefix: usize, len: usize) -> usize {
+    if len == 0 {
+        prefix
+    } else {
+        prefix + 1
+    }
+}
+
+/// A unified diff of the change to `path`, empty if there is none.
+fn diff(path: &Path, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let removed = &old_lines[prefix..old_lines.len() - suffix];
+    let added = &new_lines[prefix..new_lines.len() - suffix];
+    let mut out = format!(
+        "--- {path}\n+++ {path}\n@@ -{},{} +{},{} @@\n",
+        hunk_start(prefix, removed.len()),
+        removed.len(),
+        hunk_start(prefix, added.len()),
+        added.len(),
+        path = path.display()
+    );
+    for line in removed {
+        out += &format!("-{line}\n");
+    }
+    for line in added {
+        out += &format!("+{line}\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{diff, insert_block, remove_block, Installation};
+    use crate::integration::Shell;
+
+    fn installation(shell: Shell) -> Installation {
+        Installation {
+            shell,
+            script: PathBuf::from("/home/user/.local/share/shai/integration/assistant"),
+            rc_file: PathBuf::from("/home/user/.rc"),
+        }
+    }
+
+    #[test]
+    fn install_is_idempotent() {
+        let block = installation(Shell::Zsh).block();
+        let rc = "export EDITOR=vim\n";
+        let installed = insert_block(rc, &block);
+        assert_eq!(installed, format!("{rc}\n{block}"));
+        assert_eq!(insert_block(&installed, &block), installed);
+        assert_eq!(remove_block(&installed), rc);
+    }
+
+    #[test]
+    fn block_is_updated_in_place() {
+        let rc = "a\n\nb\n";
+        let installed = insert_block(rc, &installation(Shell::Bash).block());
+        let moved = Installation {
+            script: PathBuf::from("/opt/shai.sh"),
+            ..installation(Shell::Bash)
+        };
+        let user_edit = installed + "alias ll='ls -l'\n";
+        let updated = insert_block(&user_edit, &moved.block());
+        assert!(updated.contains("source '/opt/shai.sh'"));
+        assert!(updated.ends_with("alias ll='ls -l'\n"));
+        assert_eq!(updated.matches("shai integration >>>").count(), 1);
+        assert_eq!(remove_block(&updated), "a\n\nb\nalias ll='ls -l'\n");
+        assert_eq!(remove_block(&installation(Shell::Bash).block()), "");
+    }
+
+    #[test]
+    fn source_lines() {
+        assert!(installation(Shell::PowerShell)
+            .source_line()
+            .starts_with(". '/home"));
+        assert!(installation(Shell::Nushell)
+            .source_line()
+            .starts_with("source `"));
+    }
+
+    #[test]
+    fn diff_of_changed_lines() {
+        let path = Path::new("rc");
+        assert_eq!(diff(path, "a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            diff(path, "a\nb\nc\n", "a\nx\nc\n"),
+            "--- rc\n+++ rc\n@@ -2,1 +2,1 @@\n-b\n+x\n"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+    PowerShell,
+}
+
+impl Shell {
+    const fn template(self) -> &'static str {
+        match self {
+            Self::Bash => include_str!("../scripts/bash_assistant.sh"),
+            Self::Zsh => include_str!("../scripts/zsh_assistant.zsh"),
+            Self::Fish => include_str!("../scripts/fish_assistant.fish"),
+            Self::Nushell => include_str!("../scripts/nushell_assistant.nu"),
+            Self::PowerShell => include_str!("../scripts/powershell_assistant.ps1"),
+        }
+    }
+
+    /// Name passed to the model in `--shell`.
+    pub(crate) const fn model_name(self) -> &'static str {
+        match self {
+            Self::Bash => "Bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "Fish",
+            Self::Nushell => "Nushell",
+            Self::PowerShell => "PowerShell",
+        }
+    }
+
+    /// The shell a `--shell` value refers to. The value is free text for the model, so this also
+    /// accepts the names of the executables.
+    pub(crate) fn from_model_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            "nushell" | "nu" => Some(Self::Nushell),
+            "powershell" | "pwsh" => Some(Self::PowerShell),
+            _ => None,
+        }
+    }
+
+    pub(crate) const fn default_operating_system(self) -> &'static str {
+        match self {
+            Self::PowerShell => "Windows",
+            _ => "Linux",
+        }
+    }
+
+    /// Quote `arg` so that the shell passes it to shai as a single argument, taking into account
+    /// the string it is embedded in within the template.
+    fn quote(self, arg: &str) -> String {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c));
+        if plain {
+            return arg.to_string();
+        }
+        match self {
+            Self::Bash | Self::Zsh => format!("'{}'", arg.replace('\'', r"'\''")),
+            Self::Fish => format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'")),
+            // the invocation lives inside the double quoted `cmd` string of the keybinding
+            Self::Nushell if arg.contains('\'') => format!("`{arg}`"),
+            Self::Nushell => format!("'{arg}'"),
+            // the invocation lives inside the double quoted -ArgumentList string
+            Self::PowerShell => {
+                let escaped = arg
+                    .replace('`', "``")
+                    .replace('$', "`$")
+                    .replace('"', "\\`\"");
+                format!("`\"{escaped}`\"")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Modifier {
+    Alt,
+    Ctrl,
+}
+
+/// A key combination like `alt-s` or `ctrl-g`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct KeyBinding {
+    modifier: Modifier,
+    key: char,
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifier, key) = s
+            .split_once(['-', '+'])
+            .ok_or_else(|| format!("Invalid key '{s}', expected e.g. alt-s or ctrl-g"))?;
+        let modifier = match modifier.to_lowercase().as_str() {
+            "alt" | "meta" => Modifier::Alt,
+            "ctrl" | "control" => Modifier::Ctrl,
+            _ => return Err(format!("Unknown modifier '{modifier}', use alt or ctrl")),
+        };
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) if key.is_ascii_alphanumeric() => Ok(Self {
+                modifier,
+                key: key.to_ascii_lowercase(),
+            }),
+            _ => Err(format!("Invalid key '{key}', use a single letter or digit")),
+        }
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.modifier {
+            Modifier::Alt => write!(f, "alt-{}", self.key),
+            Modifier::Ctrl => write!(f, "ctrl-{}", self.key),
+        }
+    }
+}
+
+impl KeyBinding {
+    /// The key in the notation of the shell's binding command.
+    fn render(self, shell: Shell) -> String {
+        let key = self.key;
+        match (shell, self.modifier) {
+            (Shell::Bash | Shell::Fish, Modifier::Alt) => format!(r"\e{key}"),
+            (Shell::Bash, Modifier::Ctrl) => format!(r"\C-{key}"),
+            (Shell::Fish, Modifier::Ctrl) => format!(r"\c{key}"),
+            (Shell::Zsh, Modifier::Alt) => format!("^[{key}"),
+            (Shell::Zsh, Modifier::Ctrl) => format!("^{}", key.to_ascii_uppercase()),
+            (Shell::Nushell, modifier) => {
+                let modifier = match modifier {
+                    Modifier::Alt => "alt",
+                    Modifier::Ctrl => "control",
+                };
+                format!("modifier: {modifier}\n            keycode: char_{key}")
+            }
+            (Shell::PowerShell, Modifier::Alt) => format!("Alt+{key}"),
+            (Shell::PowerShell, Modifier::Ctrl) => format!("Ctrl+{key}"),
+        }
+    }
+}
+
+/// Everything needed to render an integration script.
+pub(crate) struct ScriptOptions {
+    pub(crate) shell: Shell,
+    /// Value of `--model`, as accepted by the command line.
+    pub(crate) model: String,
+    /// Value of `--operating-system`, `None` to let shai detect it.
+    pub(crate) operating_system: Option<String>,
+    /// Pass `--detect` so shai detects the distro and the installed programs.
+    pub(crate) detect: bool,
+    pub(crate) ask_key: KeyBinding,
+    pub(crate) explain_key: KeyBinding,
+    pub(crate) insert_key: KeyBinding,
+    pub(crate) fix_key: KeyBinding,
+    /// Flags appended to the shai invocation of each subcommand, e.g. `--cwd`.
+    pub(crate) extra_flags: HashMap<String, Vec<String>>,
+}
+
+impl ScriptOptions {
+    /// Arguments of the shai invocation for `subcommand`, without the edit file which is added by
+    /// the template.
+    pub(crate) fn shai_args(&self, subcommand: &str) -> Vec<String> {
+        let mut args = vec![subcommand.to_string()];
+        if let Some(operating_system) = &self.operating_system {
+            args.extend(["--operating-system".to_string(), operating_system.clone()]);
+        }
+        if self.detect {
+            args.push("--detect".to_string());
+        }
+        args.extend([
+            "--shell".to_string(),
+            self.shell.model_name().to_string(),
+            "--model".to_string(),
+            self.model.clone(),
+        ]);
+        if let Some(flags) = self.extra_flags.get(subcommand) {
+            args.extend(flags.iter().cloned());
+        }
+        args
+    }
+
+    fn quoted_args(&self, subcommand: &str) -> String {
+        self.shai_args(subcommand)
+            .iter()
+            .map(|arg| self.shell.quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub(crate) fn render(&self) -> String {
+        self.shell
+            .template()
+            .replace("{{ask_args}}", &self.quoted_args("ask"))
+            .replace("{{explain_args}}", &self.quoted_args("explain"))
+            .replace("{{fix_args}}", &self.quoted_args("fix"))
+            .replace("{{ask_key}}", &self.ask_key.render(self.shell))
+            .replace("{{explain_key}}", &self.explain_key.render(self.shell))
+            .replace("{{insert_key}}", &self.insert_key.render(self.shell))
+            .replace("{{fix_key}}", &self.fix_key.render(self.shell))
+            .replace("{{version}}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// Version of shai that generated `script`, read from its header.
+pub(crate) fn script_version(script: &str) -> Option<&str> {
+    let header = script.lines().next()?;
+    header
+        .strip_prefix("# Generated by shai ")?
+        .split_whitespace()
+        .next()
+}
+
+/// Split a string into arguments like a POSIX shell would, honoring single and double quotes
+/// and backslash escapes.
+pub(crate) fn split_args(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("Unterminated quote in '{text}'")),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => arg.extend(chars.next()),
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("Unterminated quote in '{text}'")),
+                    }
+                }
+            }
+            '\\' => current.get_or_insert_with(String::new).extend(chars.next()),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{script_version, split_args, KeyBinding, Shell};
+
+    #[test]
+    fn key_bindings() {
+        let alt_s: KeyBinding = "alt-s".parse().unwrap();
+        assert_eq!(alt_s.render(Shell::Bash), r"\es");
+        assert_eq!(alt_s.render(Shell::Zsh), "^[s");
+        assert_eq!(alt_s.render(Shell::PowerShell), "Alt+s");
+        let ctrl_g: KeyBinding = "Ctrl+G".parse().unwrap();
+        assert_eq!(ctrl_g.render(Shell::Zsh), "^G");
+        assert_eq!(ctrl_g.render(Shell::Fish), r"\cg");
+        assert!("super-s".parse::<KeyBinding>().is_err());
+        assert!("alt-enter".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn split_quoted_args() {
+        assert_eq!(
+            split_args(r#"--operating-system 'Arch Linux' --shell "B\"sh" a\ b"#).unwrap(),
+            vec![
+                "--operating-system",
+                "Arch Linux",
+                "--shell",
+                "B\"sh",
+                "a b"
+            ]
+        );
+        assert!(split_args("'open").is_err());
+    }
+
+    #[test]
+    fn version_header() {
+        assert_eq!(
+            script_version("# Generated by shai 0.3.1 with `shai generate-script`\n"),
+            Some("0.3.1")
+        );
+        assert_eq!(script_version("# my own script\n"), None);
+    }
+}
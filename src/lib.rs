@@ -1,20 +1,34 @@
 #![allow(clippy::future_not_send)]
 
 pub(crate) mod anthropic;
+mod cache;
 pub mod cli;
+mod config;
 mod context;
+mod doctor;
+mod error;
+mod failure;
+mod insertion;
+mod install;
+mod integration;
 mod model;
 mod openai;
 mod prompts;
+mod redact;
 pub(crate) mod sse_parser;
+mod usage;
+mod viewport;
 
 use anthropic::AnthropicModel;
 use context::Context;
+pub(crate) use error::ModelError;
+use error::Provider;
 use futures::Stream;
 use model::Task;
 use openai::OpenAIGPTModel;
 use serde::Deserialize;
-use thiserror::Error;
+use sse_parser::StreamChunk;
+use std::path::PathBuf;
 
 enum ConfigKind {
     Ask(AskConfig),
@@ -34,10 +48,27 @@ impl ConfigKind {
 struct AskConfig {
     operating_system: String,
     shell: String,
+    /// Relevant programs found in `PATH` by `--detect`.
+    tools: Option<Vec<String>>,
     environment: Option<Vec<String>>,
+    /// Variables whose value is sent, names or globs like `AWS_*`.
+    environment_values: Option<Vec<String>>,
     programs: Option<Vec<String>>,
     cwd: Option<()>,
     depth: Option<u32>,
+    history: Option<usize>,
+    /// History file passed by the integration, instead of `$HISTFILE`.
+    history_file: Option<PathBuf>,
+    versions: Option<()>,
+    git: Option<()>,
+    project: Option<()>,
+    kubernetes: Option<()>,
+    containers: Option<()>,
+    ssh_hosts: Option<()>,
+    /// Files attached with `--file`.
+    files: Option<Vec<PathBuf>>,
+    /// What was piped into shai, sampled.
+    stdin: Option<String>,
     model: ModelKind,
 }
 
@@ -45,10 +76,27 @@ struct AskConfig {
 struct ExplainConfig {
     operating_system: String,
     shell: String,
+    /// Relevant programs found in `PATH` by `--detect`.
+    tools: Option<Vec<String>>,
     environment: Option<Vec<String>>,
+    /// Variables whose value is sent, names or globs like `AWS_*`.
+    environment_values: Option<Vec<String>>,
     model: ModelKind,
     cwd: Option<()>,
     depth: Option<u32>,
+    history: Option<usize>,
+    /// History file passed by the integration, instead of `$HISTFILE`.
+    history_file: Option<PathBuf>,
+    versions: Option<()>,
+    git: Option<()>,
+    project: Option<()>,
+    kubernetes: Option<()>,
+    containers: Option<()>,
+    ssh_hosts: Option<()>,
+    /// Files attached with `--file`.
+    files: Option<Vec<PathBuf>>,
+    /// What was piped into shai, sampled.
+    stdin: Option<String>,
 }
 
 impl Default for AskConfig {
@@ -56,10 +104,22 @@ impl Default for AskConfig {
         Self {
             operating_system: "Linux".to_string(),
             shell: "Bash".to_string(),
+            tools: None,
             environment: None,
+            environment_values: None,
             programs: None,
             cwd: None,
             depth: None,
+            history: None,
+            history_file: None,
+            versions: None,
+            git: None,
+            project: None,
+            kubernetes: None,
+            containers: None,
+            ssh_hosts: None,
+            files: None,
+            stdin: None,
             model: ModelKind::OpenAIGPT(OpenAIGPTModel::GPT4oMini),
         }
     }
@@ -70,9 +130,21 @@ impl Default for ExplainConfig {
         Self {
             operating_system: "Linux".to_string(),
             shell: "Bash".to_string(),
+            tools: None,
             environment: None,
+            environment_values: None,
             cwd: None,
             depth: None,
+            history: None,
+            history_file: None,
+            versions: None,
+            git: None,
+            project: None,
+            kubernetes: None,
+            containers: None,
+            ssh_hosts: None,
+            files: None,
+            stdin: None,
             model: ModelKind::OpenAIGPT(OpenAIGPTModel::GPT4oMini),
         }
     }
@@ -86,15 +158,26 @@ enum ModelKind {
     // Local // ?
 }
 
-#[derive(Debug, Error)]
-pub(crate) enum ModelError {
-    #[error("{0}")]
-    Error(String),
-}
+impl ModelKind {
+    const fn provider(&self) -> Provider {
+        match self {
+            Self::OpenAIGPT(_) => Provider::OpenAI,
+            Self::Anthropic(_) => Provider::Anthropic,
+        }
+    }
+
+    fn api_name(&self) -> String {
+        match self {
+            Self::OpenAIGPT(model) => model.api_name(),
+            Self::Anthropic(model) => model.api_name(),
+        }
+    }
 
-impl From<Box<dyn std::error::Error + Send>> for ModelError {
-    fn from(e: Box<dyn std::error::Error + Send>) -> Self {
-        Self::Error(e.to_string())
+    const fn context_window(&self) -> usize {
+        match self {
+            Self::OpenAIGPT(model) => model.context_window(),
+            Self::Anthropic(model) => model.context_window(),
+        }
     }
 }
 
@@ -106,14 +189,8 @@ async fn model_request(
     task: Task,
 ) -> Result<String, ModelError> {
     match model {
-        ModelKind::OpenAIGPT(model) => model
-            .send(request, context, task)
-            .await
-            .map_err(|err| ModelError::Error(err.to_string())),
-        ModelKind::Anthropic(model) => model
-            .send(request, context, task)
-            .await
-            .map_err(|err| ModelError::Error(err.to_string())),
+        ModelKind::OpenAIGPT(model) => Ok(model.send(request, context, task).await?),
+        ModelKind::Anthropic(model) => Ok(model.send(request, context, task).await?),
     }
 }
 
@@ -122,21 +199,25 @@ async fn model_stream_request(
     request: String,
     context: Context,
     task: Task,
-) -> Result<impl Stream<Item = Result<String, ModelError>> + Send, ModelError> {
+    partial_response: Option<String>,
+) -> Result<impl Stream<Item = Result<StreamChunk, ModelError>> + Send, ModelError> {
     match model {
-        ModelKind::OpenAIGPT(model) => model
-            .send_streaming(request, context, task)
-            .await
-            .map_err(|e| ModelError::Error(e.to_string())),
-        ModelKind::Anthropic(model) => model
-            .send_streaming(request, context, task)
-            .await
-            .map_err(|e| ModelError::Error(e.to_string())),
+        ModelKind::OpenAIGPT(model) => Ok(model
+            .send_streaming(request, context, task, partial_response)
+            .await?),
+        ModelKind::Anthropic(model) => Ok(model
+            .send_streaming(request, context, task, partial_response)
+            .await?),
     }
 }
 
 fn build_context_request(request: &str, context: Context) -> String {
-    String::from(context) + &format!("Here is your <task>: \n <task>{request}</task>")
+    String::from(context) + &task_request(request)
+}
+
+/// The part of the request that holds the user's task.
+fn task_request(request: &str) -> String {
+    format!("Here is your <task>: \n <task>{request}</task>")
 }
 
 // #[cfg(test)]
@@ -1,9 +1,45 @@
+use crate::prompts;
+
 // pub(crate) trait Model {
 //     fn send(&self, request: String, context: Context, task: Task)
 //         -> Result<String, Box<dyn std::error::Error>>;
 // }
 
+#[derive(Clone, Copy)]
 pub(crate) enum Task {
     GenerateCommand,
+    /// Generate only the part of a command that goes at the cursor of the command line.
+    InsertFragment,
     Explain,
+    /// Diagnose a failed command and propose a corrected one.
+    FixCommand,
+    /// Explain the output of a command, e.g. a compiler error or a kernel log.
+    AnalyzeOutput,
+    /// Convert a command to another shell, flagging what has no equivalent.
+    Translate,
+}
+
+impl Task {
+    pub(crate) const fn system_prompt(&self) -> &'static str {
+        match self {
+            Self::GenerateCommand => prompts::ASK_MODEL_TASK,
+            Self::InsertFragment => prompts::INSERT_MODEL_TASK,
+            Self::Explain => prompts::EXPLAIN_MODEL_TASK,
+            Self::FixCommand => prompts::FIX_MODEL_TASK,
+            Self::AnalyzeOutput => prompts::ANALYZE_MODEL_TASK,
+            Self::Translate => prompts::TRANSLATE_MODEL_TASK,
+        }
+    }
+
+    /// Short name used in the usage ledger.
+    pub(crate) const fn name(&self) -> &'static str {
+        match self {
+            Self::GenerateCommand => "ask",
+            Self::InsertFragment => "insert",
+            Self::Explain => "explain",
+            Self::FixCommand => "fix",
+            Self::AnalyzeOutput => "analyze",
+            Self::Translate => "translate",
+        }
+    }
 }
@@ -12,9 +12,11 @@ use futures_util::StreamExt;
 
 use crate::build_context_request;
 use crate::context::Context;
+use crate::error::{ErrorKind, Provider};
 use crate::model::Task;
 use crate::prompts;
-use crate::sse_parser::ModelStream;
+use crate::sse_parser::{ModelStream, SseError, SseEvent, StreamChunk};
+use crate::usage::Usage;
 use crate::ModelError;
 
 #[derive(Deserialize)]
@@ -73,7 +75,7 @@ pub(crate) enum OpenAIGPTModel {
 }
 
 impl OpenAIGPTModel {
-    fn api_name(&self) -> String {
+    pub(crate) fn api_name(&self) -> String {
         match self {
             Self::GPT4_1 => "gpt-4.1".to_string(),
             Self::GPT4_1Mini => "gpt-4.1-mini".to_string(),
@@ -89,6 +91,16 @@ impl OpenAIGPTModel {
         }
     }
 
+    /// Tokens the model accepts in a request, response included.
+    pub(crate) const fn context_window(&self) -> usize {
+        match self {
+            Self::GPT4_1 | Self::GPT4_1Mini | Self::GPT4_1Nano => 1_047_576,
+            Self::GPT4o | Self::GPT4oMini | Self::GPT4Turbo => 128_000,
+            Self::O3 | Self::O3Mini | Self::O4Mini | Self::O1 => 200_000,
+            Self::GPT4 => 8_192,
+        }
+    }
+
     /// Returns true for o-series reasoning models that do not accept a `temperature` parameter.
     const fn is_o_series(&self) -> bool {
         matches!(self, Self::O1 | Self::O3 | Self::O3Mini | Self::O4Mini)
@@ -101,14 +113,76 @@ pub(crate) enum OpenAIError {
     Authentication(String),
     #[error("Client failed to initialize: {0}")]
     Client(#[from] reqwest::Error),
+    #[error("Request failed: {0}")]
+    Request(reqwest::Error),
     #[error("Stream was interrupted: {0}")]
     Stream(String),
-    #[error("Error Response: {0}")]
-    ErrorResponse(OpenAIErrorResponse),
+    #[error("Error Response ({status}): {body}")]
+    ErrorResponse {
+        status: u16,
+        request_id: Option<String>,
+        body: OpenAIErrorResponse,
+    },
     #[error("An unknown error happened: {0}")]
     Unknown(String),
 }
 
+impl OpenAIError {
+    /// Build an `ErrorResponse` from a non-successful HTTP response. Bodies that are not the
+    /// usual OpenAI error object (e.g. from a proxy) are kept verbatim as the message.
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let request_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(ToString::to_string);
+        let text = response.text().await.unwrap_or_default();
+        let body = serde_json::from_str(&text).unwrap_or(OpenAIErrorResponse {
+            error: OpenAIErrorResponseContent {
+                message: text,
+                r#type: None,
+                param: None,
+                code: None,
+            },
+        });
+        Self::ErrorResponse {
+            status,
+            request_id,
+            body,
+        }
+    }
+}
+
+impl From<OpenAIError> for ModelError {
+    fn from(err: OpenAIError) -> Self {
+        let provider = Provider::OpenAI;
+        match err {
+            OpenAIError::Authentication(message) => {
+                Self::new(ErrorKind::Authentication, provider, message)
+            }
+            OpenAIError::Client(err) | OpenAIError::Request(err) => {
+                Self::from_reqwest(provider, &err.without_url())
+            }
+            OpenAIError::Stream(message) => {
+                Self::new(ErrorKind::StreamInterrupted, provider, message)
+            }
+            OpenAIError::ErrorResponse {
+                status,
+                request_id,
+                body,
+            } => {
+                let error_type = body.error.code.as_deref().or(body.error.r#type.as_deref());
+                let kind = ErrorKind::classify(Some(status), error_type, &body.error.message);
+                Self::new(kind, provider, body.error.message)
+                    .with_status(status)
+                    .with_request_id(request_id)
+            }
+            OpenAIError::Unknown(message) => Self::new(ErrorKind::Unknown, provider, message),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct OpenAIErrorResponse {
     error: OpenAIErrorResponseContent,
@@ -123,11 +197,9 @@ impl Display for OpenAIErrorResponse {
 #[derive(Debug, Deserialize)]
 struct OpenAIErrorResponseContent {
     message: String,
-    #[allow(unused)]
     r#type: Option<String>,
     #[allow(unused)]
     param: Option<String>,
-    #[allow(unused)]
     code: Option<String>,
 }
 
@@ -137,6 +209,7 @@ impl OpenAIGPTModel {
         request: String,
         context: Context,
         task: Task,
+        partial_response: Option<String>,
         streaming: bool,
     ) -> Result<reqwest::Response, OpenAIError> {
         let client: Client = ClientBuilder::new()
@@ -144,7 +217,7 @@ impl OpenAIGPTModel {
             .build()
             .map_err(OpenAIError::Client)?;
 
-        let url = "https://api.openai.com/v1/chat/completions";
+        let url = Provider::OpenAI.api_url();
         let api_key = std::env::var("OPENAI_API_KEY").map_err(|_| {
             OpenAIError::Authentication(
                 "You need to set OPENAI_API_KEY env variable to use this model".to_string(),
@@ -164,10 +237,7 @@ impl OpenAIGPTModel {
 
         let context_request = build_context_request(&request, context);
 
-        let system_content = match task {
-            Task::GenerateCommand => prompts::ASK_MODEL_TASK,
-            Task::Explain => prompts::EXPLAIN_MODEL_TASK,
-        };
+        let system_content = task.system_prompt();
 
         let mut body = json!({
             "model": self.api_name(),
@@ -180,6 +250,15 @@ impl OpenAIGPTModel {
         if !self.is_o_series() {
             body["temperature"] = json!(0);
         }
+        if streaming {
+            body["stream_options"] = json!({"include_usage": true});
+        }
+        if let Some(partial_response) = partial_response {
+            if let Some(messages) = body["messages"].as_array_mut() {
+                messages.push(json!({"role": "assistant", "content": partial_response}));
+                messages.push(json!({"role": "user", "content": prompts::RESUME_PARTIAL_RESPONSE}));
+            }
+        }
 
         client
             .post(url)
@@ -187,9 +266,7 @@ impl OpenAIGPTModel {
             .json(&body)
             .send()
             .await
-            .map_err(|err| {
-                OpenAIError::Unknown(format!("Unknown request error: {}", err.without_url()))
-            })
+            .map_err(OpenAIError::Request)
     }
 
     pub(crate) async fn send(
@@ -198,14 +275,12 @@ impl OpenAIGPTModel {
         context: Context,
         task: Task,
     ) -> Result<String, OpenAIError> {
-        let response = self.send_request(request, context, task, false).await?;
+        let response = self
+            .send_request(request, context, task, None, false)
+            .await?;
 
         if response.status() != StatusCode::OK {
-            let error: OpenAIErrorResponse = response
-                .json()
-                .await
-                .map_err(|err| OpenAIError::Unknown(err.to_string()))?;
-            return Err(OpenAIError::ErrorResponse(error));
+            return Err(OpenAIError::from_response(response).await);
         }
 
         let response: Response = response
@@ -246,6 +321,33 @@ struct ResponseChunk {
     #[allow(unused)]
     model: String,
     choices: Vec<Choice>,
+    /// Only set in the last chunk, when `stream_options.include_usage` is requested
+    usage: Option<ChunkUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    reasoning_tokens: Option<u64>,
+}
+
+impl From<ChunkUsage> for Usage {
+    fn from(usage: ChunkUsage) -> Self {
+        Self {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .and_then(|details| details.reasoning_tokens)
+                .unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -261,29 +363,50 @@ enum MessageChunk {
     Stop {},
 }
 
-/// Provider-specific parser for OpenAI SSE data payloads.
+/// Provider-specific parser for OpenAI SSE events.
 /// Extracts text content from streaming chat completion chunks.
-fn parse_openai_message(json_str: &str) -> Result<Vec<String>, String> {
-    let chunk: ResponseChunk =
-        serde_json::from_str(json_str).map_err(|e| format!("OpenAI JSON parse error: {e}"))?;
-    let texts = chunk
+fn parse_openai_message(event: &SseEvent) -> Result<Vec<StreamChunk>, ModelError> {
+    if event.data == "[DONE]" {
+        return Ok(vec![StreamChunk::Done]);
+    }
+    // errors can be sent in the middle of a stream that started with a 200 status
+    if let Ok(error) = serde_json::from_str::<OpenAIErrorResponse>(&event.data) {
+        let error_type = error
+            .error
+            .code
+            .as_deref()
+            .or(error.error.r#type.as_deref());
+        let kind = ErrorKind::classify(None, error_type, &error.error.message);
+        return Err(ModelError::new(kind, Provider::OpenAI, error.error.message));
+    }
+    let chunk: ResponseChunk = serde_json::from_str(&event.data).map_err(|e| {
+        ModelError::new(
+            ErrorKind::InvalidResponse,
+            Provider::OpenAI,
+            format!("OpenAI JSON parse error: {e}"),
+        )
+    })?;
+    let mut chunks: Vec<StreamChunk> = chunk
         .choices
         .iter()
         .filter_map(|c| {
             if let MessageChunk::Content { content } = &c.delta {
                 if !content.is_empty() {
-                    return Some(content.clone());
+                    return Some(StreamChunk::Text(content.clone()));
                 }
             }
             None
         })
         .collect();
-    Ok(texts)
+    if let Some(usage) = chunk.usage {
+        chunks.push(StreamChunk::Usage(usage.into()));
+    }
+    Ok(chunks)
 }
 
-impl From<String> for OpenAIError {
-    fn from(s: String) -> Self {
-        Self::Stream(s)
+impl From<SseError> for OpenAIError {
+    fn from(err: SseError) -> Self {
+        Self::Stream(err.to_string())
     }
 }
 
@@ -293,26 +416,27 @@ impl OpenAIGPTModel {
         request: String,
         context: Context,
         task: Task,
+        partial_response: Option<String>,
     ) -> Result<ModelStream<ModelError>, OpenAIError> {
-        let response = self.send_request(request, context, task, true).await?;
+        let response = self
+            .send_request(request, context, task, partial_response, true)
+            .await?;
         if response.status() == StatusCode::OK {
             let byte_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, String>> + Send>> =
                 Box::pin(response.bytes_stream().map(|r| r.map_err(|e| e.to_string())));
-            let err_map: fn(String) -> ModelError = |s| ModelError::Error(s);
+            let err_map: fn(SseError) -> ModelError = |err| OpenAIError::from(err).into();
             Ok(ModelStream::new(byte_stream, parse_openai_message, err_map))
         } else {
-            let error: OpenAIErrorResponse = response
-                .json()
-                .await
-                .map_err(|err| OpenAIError::Unknown(err.to_string()))?;
-            Err(OpenAIError::ErrorResponse(error))
+            Err(OpenAIError::from_response(response).await)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Choice, MessageChunk, ResponseChunk};
+    use super::{parse_openai_message, Choice, MessageChunk, ResponseChunk};
+    use crate::error::ErrorKind;
+    use crate::sse_parser::SseEvent;
     #[cfg(feature = "live-api-tests")]
     use super::OpenAIGPTModel;
 
@@ -334,6 +458,16 @@ mod tests {
         serde_json::from_str::<ResponseChunk>(raw_response).unwrap();
     }
 
+    #[test]
+    fn in_band_error() {
+        let event = SseEvent {
+            data: r#"{"error":{"message":"Rate limit reached","type":"requests","param":null,"code":"rate_limit_exceeded"}}"#.to_string(),
+            ..SseEvent::default()
+        };
+        let error = parse_openai_message(&event).err().unwrap();
+        assert_eq!(error.kind, ErrorKind::RateLimit);
+    }
+
     /// Integration tests that make real OpenAI API calls.
     /// Requires the `live-api-tests` feature and a valid `OPENAI_API_KEY` env var.
     ///
@@ -343,6 +477,7 @@ mod tests {
         use super::OpenAIGPTModel;
         use crate::context::Context;
         use crate::model::Task;
+        use crate::sse_parser::StreamChunk;
         use crate::{AskConfig, ConfigKind};
         use futures_util::StreamExt;
 
@@ -377,7 +512,12 @@ mod tests {
         async fn assert_send_streaming(model: OpenAIGPTModel) {
             dotenvy::dotenv().ok();
             let stream = model
-                .send_streaming(PROMPT.to_string(), default_context(), Task::GenerateCommand)
+                .send_streaming(
+                    PROMPT.to_string(),
+                    default_context(),
+                    Task::GenerateCommand,
+                    None,
+                )
                 .await;
             assert!(
                 stream.is_ok(),
@@ -390,7 +530,10 @@ mod tests {
                 .collect::<Vec<_>>()
                 .await
                 .into_iter()
-                .map(|chunk| chunk.expect("stream chunk error"))
+                .filter_map(|chunk| match chunk.expect("stream chunk error") {
+                    StreamChunk::Text(text) => Some(text),
+                    _ => None,
+                })
                 .collect();
             assert!(
                 !response.is_empty(),
@@ -1,98 +1,195 @@
+use std::collections::VecDeque;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use bytes::Bytes;
 use futures::stream::Stream;
 use futures::StreamExt;
+use thiserror::Error;
 
-/// Parses a raw byte stream into SSE data payloads.
-///
-/// SSE events are lines of `data: ...` terminated by a blank line.
-/// `SSEParser` aggregates those lines and yields each complete payload
-/// as `Some(String)`.  The special `data: [DONE]` sentinel yields
-/// `None`, which terminates the stream via `flat_map`.
-struct SSEParser<S> {
-    inner: S,
-    acc: String,
+use crate::usage::Usage;
+
+/// A single server-sent event as described by the
+/// [SSE spec](https://html.spec.whatwg.org/multipage/server-sent-events.html).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SseEvent {
+    /// Value of the `event:` field, `None` for the default `message` type.
+    pub(crate) event: Option<String>,
+    /// All `data:` lines of the event joined with `\n`.
+    pub(crate) data: String,
+    /// Last event id seen on the stream, ids persist across events.
+    pub(crate) id: Option<String>,
 }
 
-impl<S> SSEParser<S>
-where
-    S: Stream<Item = Result<Bytes, String>>,
-{
-    fn new(inner: S) -> Self {
-        Self {
-            inner,
-            acc: String::new(),
-        }
+impl SseEvent {
+    /// The event type, defaulting to `message` when no `event:` field was sent.
+    pub(crate) fn event_type(&self) -> &str {
+        self.event.as_deref().unwrap_or("message")
     }
+}
 
-    /// Drain one complete SSE block from `self.acc` if available.
-    fn poll_block(&mut self) -> Option<Option<String>> {
-        if self.acc.starts_with("data: [DONE]") {
-            self.acc.clear();
-            return Some(None);
-        }
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub(crate) enum SseError {
+    /// The underlying byte stream failed.
+    #[error("{0}")]
+    Transport(String),
+}
 
-        if let Some(pos) = self.acc.find("\n\n") {
-            let block = self.acc[..pos].to_string();
-            self.acc = self.acc[pos + 2..].to_string();
+/// Incremental SSE decoder.
+///
+/// Bytes are buffered until a full line is available so that multi-byte UTF-8 characters split
+/// across network chunks are decoded correctly. Lines may end in `\n`, `\r\n` or `\r`.
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+    /// A `\r` ended the last line, so a leading `\n` in the next chunk belongs to it.
+    pending_cr: bool,
+    event: Option<String>,
+    data: Option<String>,
+    last_id: Option<String>,
+}
 
-            let mut payload = String::new();
-            for line in block.lines() {
-                if let Some(rest) = line.strip_prefix("data: ") {
-                    if !payload.is_empty() {
-                        payload.push('\n');
-                    }
-                    payload.push_str(rest);
+impl SseDecoder {
+    /// Feed bytes into the decoder, returning the events completed by them.
+    fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut bytes = bytes;
+        if self.pending_cr {
+            self.pending_cr = false;
+            if let Some(rest) = bytes.strip_prefix(b"\n") {
+                bytes = rest;
+            }
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.buffer.len() {
+            let end_len = match self.buffer[i] {
+                b'\n' => 1,
+                b'\r' if self.buffer.get(i + 1) == Some(&b'\n') => 2,
+                b'\r' if i + 1 == self.buffer.len() => {
+                    self.pending_cr = true;
+                    1
                 }
+                b'\r' => 1,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let line = String::from_utf8_lossy(&self.buffer[start..i]).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
             }
+            i += end_len;
+            start = i;
+        }
+        self.buffer.drain(..start);
+        events
+    }
 
-            if payload.is_empty() {
-                None
-            } else {
-                Some(Some(payload))
+    /// Flush whatever is left once the byte stream ends.
+    ///
+    /// The spec discards an event that is not terminated by a blank line, but some servers
+    /// close the connection right after the last `data:` line, so it is dispatched instead.
+    fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
             }
-        } else {
-            None
         }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // comment, usually a keep-alive
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            // we never reconnect, so the reconnection time is of no use
+            "retry" => (),
+            _ => (),
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        self.data.take().map(|data| SseEvent {
+            event,
+            data,
+            id: self.last_id.clone(),
+        })
     }
 }
 
-impl<S> Stream for SSEParser<S>
+/// Decodes a raw byte stream into [`SseEvent`]s.
+struct SseStream<S> {
+    inner: S,
+    decoder: SseDecoder,
+    ready: VecDeque<SseEvent>,
+    finished: bool,
+}
+
+impl<S> SseStream<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            decoder: SseDecoder::default(),
+            ready: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<S> Stream for SseStream<S>
 where
     S: Stream<Item = Result<Bytes, String>> + Unpin,
 {
-    type Item = Option<String>;
+    type Item = Result<SseEvent, SseError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         loop {
-            if let Some(block) = self.poll_block() {
-                return Poll::Ready(Some(block));
+            if let Some(event) = self.ready.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if self.finished {
+                return Poll::Ready(None);
             }
 
             match Pin::new(&mut self.inner).poll_next(cx) {
                 Poll::Ready(Some(Ok(bytes))) => {
-                    self.acc.push_str(&String::from_utf8_lossy(&bytes));
+                    let events = self.decoder.push(&bytes);
+                    self.ready.extend(events);
                 }
                 Poll::Ready(Some(Err(e))) => {
-                    return Poll::Ready(Some(Some(format!("__SSE_ERROR__:{e}"))));
+                    self.finished = true;
+                    return Poll::Ready(Some(Err(SseError::Transport(e))));
                 }
                 Poll::Ready(None) => {
-                    if self.acc.is_empty() {
-                        return Poll::Ready(None);
-                    }
-                    let remaining = std::mem::take(&mut self.acc);
-                    if remaining.contains("data: [DONE]") {
-                        return Poll::Ready(Some(None));
+                    self.finished = true;
+                    if let Some(event) = self.decoder.finish() {
+                        self.ready.push_back(event);
                     }
-                    if let Some(pos) = remaining.find("data: ") {
-                        let payload = remaining[pos + 6..].trim().to_string();
-                        if !payload.is_empty() {
-                            return Poll::Ready(Some(Some(payload)));
-                        }
-                    }
-                    return Poll::Ready(None);
                 }
                 Poll::Pending => return Poll::Pending,
             }
@@ -100,14 +197,25 @@ where
     }
 }
 
+/// What a provider made of a single SSE event.
+#[derive(Debug)]
+pub(crate) enum StreamChunk {
+    /// A piece of the response text.
+    Text(String),
+    /// Tokens consumed by the request so far.
+    Usage(Usage),
+    /// The provider signaled the end of the response. Consumed by `ModelStream`, never yielded.
+    Done,
+}
+
 /// Concrete stream type returned by both providers' `send_streaming`.
 ///
 /// Wraps the raw HTTP byte stream and provider-specific message
-/// extraction into a single `Stream<Item = Result<String, E>>`.
+/// extraction into a single `Stream<Item = Result<StreamChunk, E>>`.
 /// The `Pin` used internally for the raw bytes is fully encapsulated;
 /// callers interact with a concrete `ModelStream<E>` that is `Unpin`.
-pub struct ModelStream<E> {
-    inner: Pin<Box<dyn Stream<Item = Result<String, E>> + Send>>,
+pub(crate) struct ModelStream<E> {
+    inner: Pin<Box<dyn Stream<Item = Result<StreamChunk, E>> + Send>>,
 }
 
 impl<E> Unpin for ModelStream<E> {}
@@ -118,36 +226,40 @@ impl<E: 'static + Send> ModelStream<E> {
     ///
     /// `byte_stream` is the raw HTTP byte stream with `reqwest::Error` already
     /// mapped to `String`.
-    /// `parse_fn` receives a complete SSE data payload and extracts text chunks.
-    /// `err_map` converts raw SSE parse errors into the provider's error type `E`.
-    pub fn new(
+    /// `parse_fn` receives a complete SSE event and extracts text and usage chunks, or fails
+    /// when the event is malformed or reports an error.
+    /// `err_map` converts SSE transport errors into the provider's error type `E`.
+    pub(crate) fn new(
         byte_stream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>,
-        parse_fn: fn(&str) -> Result<Vec<String>, String>,
-        err_map: fn(String) -> E,
+        parse_fn: fn(&SseEvent) -> Result<Vec<StreamChunk>, E>,
+        err_map: fn(SseError) -> E,
     ) -> Self {
-        // 1. Parse raw bytes into SSE payloads (Option<String>).
-        let sse = SSEParser::new(byte_stream);
-
-        // 2. For each payload, run the provider's parse function and yield
-        //    individual text chunks, filtering empties.
-        //    An SSE byte-stream error is surfaced via err_map.
-        //    [DONE] (None) terminates the inner flat_map stream.
-        let chunks = sse.flat_map(move |payload| -> Pin<Box<dyn Stream<Item = Result<String, E>> + Send>> {
-            match payload {
-                None => Box::pin(futures::stream::empty()), // [DONE]
-                Some(s) if s.starts_with("__SSE_ERROR__:") => {
-                    let msg = s.trim_start_matches("__SSE_ERROR__:").to_string();
-                    Box::pin(futures::stream::once(async move { Err(err_map(msg)) }))
-                }
-                Some(json_str) => {
-                    let texts: Vec<Result<String, E>> = match parse_fn(&json_str) {
-                        Ok(t) => t.into_iter().filter(|s| !s.is_empty()).map(Ok).collect(),
-                        Err(_) => vec![],
-                    };
-                    Box::pin(futures::stream::iter(texts))
-                }
-            }
-        });
+        // 1. Decode raw bytes into SSE events.
+        let sse = SseStream::new(byte_stream);
+
+        // 2. For each event, run the provider's parse function and yield
+        //    individual chunks, filtering empty texts.
+        //    Any error ends the stream after being yielded, as does `StreamChunk::Done`.
+        let chunks = sse
+            .flat_map(move |event| {
+                let items: Vec<Option<Result<StreamChunk, E>>> = match event {
+                    Err(err) => vec![Some(Err(err_map(err))), None],
+                    Ok(event) => match parse_fn(&event) {
+                        Ok(parsed) => parsed
+                            .into_iter()
+                            .filter_map(|parsed| match parsed {
+                                StreamChunk::Text(text) if text.is_empty() => None,
+                                StreamChunk::Done => Some(None),
+                                chunk => Some(Some(Ok(chunk))),
+                            })
+                            .collect(),
+                        Err(err) => vec![Some(Err(err)), None],
+                    },
+                };
+                futures::stream::iter(items)
+            })
+            .take_while(|item| futures::future::ready(item.is_some()))
+            .filter_map(futures::future::ready);
 
         Self {
             inner: Box::pin(chunks),
@@ -156,10 +268,70 @@ impl<E: 'static + Send> ModelStream<E> {
 }
 
 impl<E> Stream for ModelStream<E> {
-    type Item = Result<String, E>;
+    type Item = Result<StreamChunk, E>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         // Safe because ModelStream is Unpin (the inner Pin<Box> is always Unpin).
         Pin::new(&mut self.get_mut().inner).poll_next(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{SseDecoder, SseEvent};
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::default();
+        let mut events: Vec<SseEvent> = chunks
+            .iter()
+            .flat_map(|chunk| decoder.push(chunk))
+            .collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    #[test]
+    fn event_fields() {
+        let events = decode(&[
+            b": keep-alive\n\nevent: content_block_delta\nid: 7\nretry: 100\ndata: {\"a\":1}\n\n",
+        ]);
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: Some("content_block_delta".to_string()),
+                data: "{\"a\":1}".to_string(),
+                id: Some("7".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn multiline_data_without_space() {
+        let events = decode(&[b"data:first\ndata: second\n\n"]);
+        assert_eq!(events[0].data, "first\nsecond");
+        assert_eq!(events[0].event_type(), "message");
+    }
+
+    #[test]
+    fn chunk_boundaries_and_crlf() {
+        let text = "data: héllo\r\n\r\ndata: [DONE]\r\n\r\n".as_bytes();
+        // split in the middle of the two-byte 'é' and of a "\r\n"
+        let events = decode(&[&text[..8], &text[8..13], &text[13..]]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, "héllo");
+        assert_eq!(events[1].data, "[DONE]");
+    }
+
+    #[test]
+    fn unterminated_event_is_flushed() {
+        let events = decode(&[b"event: message_stop\ndata: {}"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type(), "message_stop");
+    }
+
+    #[test]
+    fn blank_lines_without_data_dispatch_nothing() {
+        let events = decode(&[b"event: ping\n\n\n\n"]);
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, FileConfig};
+
+/// Tokens consumed by a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Usage {
+    pub(crate) input_tokens: u64,
+    pub(crate) output_tokens: u64,
+    /// Part of `output_tokens` spent on hidden reasoning, only reported by some models.
+    pub(crate) reasoning_tokens: u64,
+}
+
+impl Usage {
+    /// Merge a usage report from the same request.
+    ///
+    /// Providers report cumulative counts, possibly split across several events (Anthropic sends
+    /// the input tokens at the start and the output tokens at the end), so the largest value of
+    /// each field wins.
+    pub(crate) fn merge(&mut self, other: Self) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+        self.reasoning_tokens = self.reasoning_tokens.max(other.reasoning_tokens);
+    }
+
+    /// Add the usage of another request.
+    pub(crate) fn add(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+    }
+}
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Price {
+    pub(crate) input: f64,
+    pub(crate) output: f64,
+}
+
+impl Price {
+    const fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+
+    /// Look up the price of a model, preferring the overrides in the config file.
+    pub(crate) fn of(model: &str, config: &FileConfig) -> Option<Self> {
+        config
+            .prices
+            .get(model)
+            .copied()
+            .or_else(|| Self::default_for(model))
+    }
+
+    /// List prices at the time of writing, they can be overridden in the config file.
+    fn default_for(model: &str) -> Option<Self> {
+        let price = match model {
+            "gpt-4.1" | "o3" => Self::new(2.0, 8.0),
+            "gpt-4.1-mini" => Self::new(0.4, 1.6),
+            "gpt-4.1-nano" => Self::new(0.1, 0.4),
+            "gpt-4o" => Self::new(2.5, 10.0),
+            "gpt-4o-mini" => Self::new(0.15, 0.6),
+            "o3-mini" | "o4-mini" => Self::new(1.1, 4.4),
+            "o1" => Self::new(15.0, 60.0),
+            "gpt-4-turbo" => Self::new(10.0, 30.0),
+            "gpt-4" => Self::new(30.0, 60.0),
+            "claude-opus-4-6" | "claude-opus-4-5" => Self::new(5.0, 25.0),
+            "claude-sonnet-4-6" | "claude-sonnet-4-5" | "claude-sonnet-4-0" => Self::new(3.0, 15.0),
+            "claude-haiku-4-5" => Self::new(1.0, 5.0),
+            "claude-opus-4-0" | "claude-opus-4-1" => Self::new(15.0, 75.0),
+            _ => return None,
+        };
+        Some(price)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn cost(&self, usage: Usage) -> f64 {
+        (usage.input_tokens as f64).mul_add(self.input, usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// One line of the usage ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LedgerRecord {
+    /// Seconds since the UNIX epoch.
+    pub(crate) timestamp: u64,
+    pub(crate) provider: String,
+    pub(crate) model: String,
+    pub(crate) task: String,
+    #[serde(flatten)]
+    pub(crate) usage: Usage,
+    /// Cost in USD, `None` when the model has no known price.
+    pub(crate) cost: Option<f64>,
+}
+
+impl LedgerRecord {
+    pub(crate) fn new(
+        provider: impl Display,
+        model: String,
+        task: impl Display,
+        usage: Usage,
+        config: &FileConfig,
+    ) -> Self {
+        let cost = Price::of(&model, config).map(|price| price.cost(usage));
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            provider: provider.to_string(),
+            model,
+            task: task.to_string(),
+            usage,
+            cost,
+        }
+    }
+}
+
+/// Append-only JSON lines file with a record per request.
+pub(crate) struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub(crate) fn open_default() -> Option<Self> {
+        config::data_dir().map(|dir| Self {
+            path: dir.join("usage.jsonl"),
+        })
+    }
+
+    pub(crate) fn append(&self, record: &LedgerRecord) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let line = serde_json::to_string(record).map_err(io::Error::other)?;
+        writeln!(file, "{line}")
+    }
+
+    /// Read all records, skipping lines that cannot be parsed.
+    pub(crate) fn read(&self) -> io::Result<Vec<LedgerRecord>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        Ok(io::BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+}
+
+#[derive(Default)]
+struct ReportRow {
+    requests: u64,
+    usage: Usage,
+    cost: f64,
+    unpriced: bool,
+}
+
+impl ReportRow {
+    fn add(&mut self, record: &LedgerRecord) {
+        self.requests += 1;
+        self.usage.add(record.usage);
+        match record.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced = true,
+        }
+    }
+}
+
+/// Render a usage report table of the records, grouped by `key`.
+pub(crate) fn report(records: &[LedgerRecord], key: fn(&LedgerRecord) -> String) -> String {
+    let mut groups: BTreeMap<String, ReportRow> = BTreeMap::new();
+    let mut total = ReportRow::default();
+    for record in records {
+        groups.entry(key(record)).or_default().add(record);
+        total.add(record);
+    }
+
+    let width = groups.keys().map(String::len).max().unwrap_or(0).max(5);
+    let mut out = format!(
+        "{:<width$}  {:>8}  {:>12}  {:>12}  {:>12}  {:>10}\n",
+        "", "requests", "input", "output", "reasoning", "cost (USD)"
+    );
+    let mut push_row = |name: &str, row: &ReportRow| {
+        let marker = if row.unpriced { "*" } else { "" };
+        out += &format!(
+            "{name:<width$}  {:>8}  {:>12}  {:>12}  {:>12}  {:>10}\n",
+            row.requests,
+            row.usage.input_tokens,
+            row.usage.output_tokens,
+            row.usage.reasoning_tokens,
+            format!("{:.4}{marker}", row.cost),
+        );
+    };
+    for (name, row) in &groups {
+        push_row(name, row);
+    }
+    push_row("total", &total);
+    if total.unpriced {
+        out += "* some requests used a model without a known price, set it in the config file\n";
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, LedgerRecord, Price, Usage};
+
+    fn record(model: &str, input_tokens: u64, output_tokens: u64) -> LedgerRecord {
+        let usage = Usage {
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
+        };
+        LedgerRecord {
+            timestamp: 0,
+            provider: "OpenAI".to_string(),
+            model: model.to_string(),
+            task: "ask".to_string(),
+            usage,
+            cost: Price::default_for(model).map(|price| price.cost(usage)),
+        }
+    }
+
+    #[test]
+    fn merge_split_reports() {
+        let mut usage = Usage {
+            input_tokens: 120,
+            output_tokens: 1,
+            reasoning_tokens: 0,
+        };
+        usage.merge(Usage {
+            input_tokens: 0,
+            output_tokens: 42,
+            reasoning_tokens: 0,
+        });
+        assert_eq!(usage.input_tokens, 120);
+        assert_eq!(usage.output_tokens, 42);
+    }
+
+    #[test]
+    fn cost_per_million() {
+        let price = Price::new(2.0, 8.0);
+        let cost = price.cost(Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 500_000,
+            reasoning_tokens: 0,
+        });
+        assert!((cost - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn report_groups_and_totals() {
+        let records = vec![
+            record("gpt-4o", 1000, 100),
+            record("gpt-4o", 1000, 100),
+            record("local", 10, 10),
+        ];
+        let table = report(&records, |record| record.model.clone());
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[1].starts_with("gpt-4o") && lines[1].contains(" 2 "));
+        assert!(lines[2].starts_with("local") && lines[2].contains('*'));
+        assert!(lines[3].starts_with("total") && lines[3].contains(" 3 "));
+    }
+}
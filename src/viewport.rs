@@ -0,0 +1,296 @@
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Set in the environment of the shai launched inside a tmux popup, so it does not launch
+/// another one. Its value is the file with the API keys the popup needs, if any.
+const POPUP_ENV: &str = "SHAI_TMUX_POPUP";
+
+/// Environment variables shai needs that the tmux server may not have.
+const FORWARDED_ENV: [&str; 2] = ["SHAI_CONFIG", "PWD"];
+
+/// API keys the popup needs. Anyone can read the arguments of `tmux` from the process list, so
+/// they are written to a file only the user can read, that the popup deletes once read.
+const SECRET_ENV: [&str; 2] = ["OPENAI_API_KEY", "ANTHROPIC_API_KEY"];
+
+/// First tmux with `display-popup -e`.
+const MIN_TMUX_VERSION: (u32, u32) = (3, 2);
+
+/// Lines shai needs to draw the prompt, a response and the controls.
+const MIN_HEIGHT: u16 = 8;
+
+/// Height of the interface, like fzf's `--height`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Height {
+    Lines(u16),
+    Percent(u16),
+}
+
+impl FromStr for Height {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("Invalid height '{s}', expected lines like 20 or a percentage like 40%");
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.parse() {
+                Ok(percent @ 1..=100) => Ok(Self::Percent(percent)),
+                _ => Err(invalid()),
+            },
+            None => s.parse().map(Self::Lines).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl Display for Height {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lines(lines) => write!(f, "{lines}"),
+            Self::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl Height {
+    /// Number of lines on a terminal with `rows` rows, never less than what shai needs nor more
+    /// than the terminal has.
+    pub(crate) fn lines(self, rows: u16) -> u16 {
+        let lines = match self {
+            Self::Lines(lines) => lines,
+            Self::Percent(percent) => {
+                u16::try_from(u32::from(rows) * u32::from(percent) / 100).unwrap_or(rows)
+            }
+        };
+        lines.max(MIN_HEIGHT).min(rows)
+    }
+}
+
+/// Major and minor version from the output of `tmux -V`, like `tmux 3.3a` or `tmux next-3.4`.
+fn parse_tmux_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().nth(1)?;
+    if version == "master" {
+        return Some((u32::MAX, 0));
+    }
+    let (major, minor) = version.trim_start_matches("next-").split_once('.')?;
+    let minor: String = minor.chars().take_while(char::is_ascii_digit).collect();
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+fn tmux_version() -> Option<(u32, u32)> {
+    let output = Command::new("tmux").arg("-V").output().ok()?;
+    parse_tmux_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Whether shai should re-launch itself in a tmux popup: it was asked to, it runs inside a tmux
+/// recent enough and it is not already the popup. Warns when the popup was asked for but can't
+/// be used.
+pub(crate) fn use_tmux_popup(requested: bool) -> bool {
+    if !requested || in_tmux_popup() {
+        return false;
+    }
+    if std::env::var_os("TMUX").is_none() {
+        eprintln!("--tmux-popup is ignored outside of tmux");
+        return false;
+    }
+    match tmux_version() {
+        Some(version) if version >= MIN_TMUX_VERSION => true,
+        _ => {
+            let (major, minor) = MIN_TMUX_VERSION;
+            eprintln!("--tmux-popup needs tmux {major}.{minor} or newer, it is ignored");
+            false
+        }
+    }
+}
+
+/// Whether this is the shai launched inside a tmux popup.
+pub(crate) fn in_tmux_popup() -> bool {
+    std::env::var_os(POPUP_ENV).is_some()
+}
+
+/// Quote `arg` for a POSIX shell, tmux runs the popup command through one.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Arguments of `tmux` to run `command` in a popup of the given height.
+fn popup_args(command: &[String], height: Option<Height>, env: &[(&str, String)]) -> Vec<String> {
+    let mut args = vec![
+        "display-popup".to_string(),
+        "-E".to_string(),
+        "-w".to_string(),
+        "80%".to_string(),
+        "-h".to_string(),
+        height.unwrap_or(Height::Percent(60)).to_string(),
+    ];
+    if let Ok(cwd) = std::env::current_dir() {
+        args.extend(["-d".to_string(), cwd.to_string_lossy().to_string()]);
+    }
+    for (name, value) in env {
+        args.extend(["-e".to_string(), format!("{name}={value}")]);
+    }
+    args.push(
+        command
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    args
+}
+
+/// Write `secrets` as `NAME=value` lines to a new file in `dir` that only the user can read.
+fn write_secrets(dir: &Path, secrets: &[(&str, String)]) -> io::Result<PathBuf> {
+    let path = dir.join(format!("shai-popup-{}", std::process::id()));
+    let mut options = OpenOptions::new();
+    // never follow a file or symlink that is already there
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path)?;
+    for (name, value) in secrets {
+        writeln!(file, "{name}={value}")?;
+    }
+    Ok(path)
+}
+
+/// Read the secrets written by [`write_secrets`] and delete the file.
+fn take_secrets(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let contents = fs::read_to_string(path);
+    fs::remove_file(path)?;
+    Ok(contents?
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(name, _)| SECRET_ENV.contains(name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect())
+}
+
+/// In the popup, set the API keys left by the shai that opened it. Must run before anything
+/// reads the environment.
+pub(crate) fn load_popup_secrets() -> io::Result<()> {
+    let Some(path) = std::env::var_os(POPUP_ENV).filter(|path| !path.is_empty()) else {
+        return Ok(());
+    };
+    for (name, value) in take_secrets(Path::new(&path))? {
+        std::env::set_var(name, value);
+    }
+    Ok(())
+}
+
+/// Run this same shai invocation inside a tmux popup and wait for it to close. The popup
+/// writes its result to the edit file, exactly as it would outside of tmux.
+pub(crate) fn run_in_tmux_popup(height: Option<Height>) -> io::Result<()> {
+    let command: Vec<String> = std::env::current_exe()
+        .map(OsString::from)
+        .into_iter()
+        .chain(std::env::args_os().skip(1))
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+    let secrets: Vec<(&str, String)> = SECRET_ENV
+        .iter()
+        .filter_map(|name| Some((*name, std::env::var(name).ok()?)))
+        .collect();
+    let secrets_file = if secrets.is_empty() {
+        None
+    } else {
+        Some(write_secrets(&std::env::temp_dir(), &secrets)?)
+    };
+    let popup_value = secrets_file
+        .as_ref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut env = vec![(POPUP_ENV, popup_value)];
+    env.extend(
+        FORWARDED_ENV
+            .iter()
+            .filter_map(|name| Some((*name, std::env::var(name).ok()?))),
+    );
+    let status = Command::new("tmux")
+        .args(popup_args(&command, height, &env))
+        .status();
+    // the popup deletes it once read, unless it failed before reading it
+    if let Some(path) = secrets_file {
+        let _ = fs::remove_file(path);
+    }
+    let status = status?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "tmux display-popup failed: {status}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_tmux_version, popup_args, take_secrets, write_secrets, Height};
+
+    #[test]
+    fn parse_height() {
+        assert_eq!("40%".parse(), Ok(Height::Percent(40)));
+        assert_eq!("15".parse(), Ok(Height::Lines(15)));
+        assert!("0%".parse::<Height>().is_err());
+        assert!("tall".parse::<Height>().is_err());
+    }
+
+    #[test]
+    fn height_in_lines() {
+        assert_eq!(Height::Percent(40).lines(50), 20);
+        assert_eq!(Height::Percent(10).lines(50), 8);
+        assert_eq!(Height::Lines(100).lines(50), 50);
+    }
+
+    #[test]
+    fn popup_command_is_quoted() {
+        let command = ["shai".to_string(), "ask".to_string(), "it's".to_string()];
+        let args = popup_args(
+            &command,
+            Some(Height::Lines(20)),
+            &[("PWD", "/tmp".to_string())],
+        );
+        assert_eq!(args[..6], ["display-popup", "-E", "-w", "80%", "-h", "20"]);
+        assert!(args.windows(2).any(|pair| pair == ["-e", "PWD=/tmp"]));
+        assert_eq!(args.last().unwrap(), r"'shai' 'ask' 'it'\''s'");
+    }
+
+    #[test]
+    fn tmux_versions() {
+        assert_eq!(parse_tmux_version("tmux 3.3a\n"), Some((3, 3)));
+        assert_eq!(parse_tmux_version("tmux 3.2"), Some((3, 2)));
+        assert_eq!(parse_tmux_version("tmux next-3.5"), Some((3, 5)));
+        assert!(parse_tmux_version("tmux 2.9a") < Some((3, 2)));
+        assert!(parse_tmux_version("tmux master") > Some((3, 2)));
+        assert_eq!(parse_tmux_version("bash: tmux: command not found"), None);
+    }
+
+    #[test]
+    fn secrets_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = [
+            ("OPENAI_API_KEY", "sk-1=2".to_string()),
+            ("ANTHROPIC_API_KEY", "sk-ant".to_string()),
+        ];
+        let path = write_secrets(dir.path(), &secrets).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(write_secrets(dir.path(), &secrets).is_err());
+        let read = take_secrets(&path).unwrap();
+        assert_eq!(
+            read[0],
+            ("OPENAI_API_KEY".to_string(), "sk-1=2".to_string())
+        );
+        assert_eq!(read.len(), 2);
+        assert!(!path.exists());
+    }
+}